@@ -0,0 +1,13 @@
+-- Baseline: same table the bot used to create on first start
+CREATE TABLE IF NOT EXISTS deal
+(
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    deal_id         BIGINTEGER          NOT NULL,
+    project         TEXT                NOT NULL,
+    house           INTEGER             NOT NULL,
+    object_type     TEXT                NOT NULL,
+    object          INTEGER             NOT NULL,
+    facing          TEXT,
+    created_on      DATETIME DEFAULT    (datetime('now', 'localtime')),
+    updated_on      DATETIME DEFAULT    (datetime('now', 'localtime'))
+);
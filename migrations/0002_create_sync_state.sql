@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS sync_state
+(
+    project         TEXT PRIMARY KEY,
+    updated_at      INTEGER             NOT NULL
+);
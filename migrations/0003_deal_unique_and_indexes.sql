@@ -0,0 +1,5 @@
+-- Keep the first stored row of every deal before enforcing uniqueness
+DELETE FROM deal WHERE id NOT IN (SELECT MIN(id) FROM deal GROUP BY deal_id);
+
+CREATE UNIQUE INDEX deal_deal_id_uq ON deal (deal_id);
+CREATE INDEX deal_lookup_idx ON deal (project, object_type, house, object);
@@ -0,0 +1,3 @@
+ALTER TABLE deal ADD COLUMN handover_status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE deal ADD COLUMN handover_date DATETIME;
+ALTER TABLE deal ADD COLUMN handed_by TEXT;
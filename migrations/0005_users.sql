@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS users
+(
+    user_id         BIGINTEGER PRIMARY KEY,
+    name            TEXT                NOT NULL,
+    role            TEXT                NOT NULL,
+    invited_by      BIGINTEGER,
+    created_on      DATETIME DEFAULT    (datetime('now', 'localtime'))
+);
+
+CREATE TABLE IF NOT EXISTS invite
+(
+    token           TEXT PRIMARY KEY,
+    role            TEXT                NOT NULL,
+    created_by      BIGINTEGER          NOT NULL,
+    created_on      DATETIME DEFAULT    (datetime('now', 'localtime')),
+    used_by         BIGINTEGER
+);
@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS sync_run
+(
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at      DATETIME            NOT NULL,
+    finished_at     DATETIME            NOT NULL,
+    -- 'cron' or id of the Telegram user who ran /sync
+    trigger         TEXT                NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sync_run_project
+(
+    run_id          INTEGER             NOT NULL REFERENCES sync_run (id),
+    project         TEXT                NOT NULL,
+    leads_seen      INTEGER             NOT NULL,
+    -- Space separated ids of the deals stored by this run
+    new_deals       TEXT                NOT NULL,
+    error           TEXT
+);
+
+CREATE INDEX IF NOT EXISTS sync_run_project_run_idx ON sync_run_project (run_id);
@@ -0,0 +1,10 @@
+-- Deals Profitbase failed to return, retried on every sync until stored
+CREATE TABLE IF NOT EXISTS quarantine
+(
+    deal_id         BIGINTEGER PRIMARY KEY,
+    project         TEXT                NOT NULL,
+    reason          TEXT                NOT NULL,
+    attempts        INTEGER             NOT NULL DEFAULT 1,
+    first_failed    DATETIME DEFAULT    (datetime('now', 'localtime')),
+    last_failed     DATETIME DEFAULT    (datetime('now', 'localtime'))
+);
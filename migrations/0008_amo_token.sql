@@ -0,0 +1,9 @@
+-- AmoCRM OAuth2 tokens, rotated on every refresh
+CREATE TABLE IF NOT EXISTS amo_token
+(
+    project         TEXT PRIMARY KEY,
+    access_token    TEXT                NOT NULL,
+    refresh_token   TEXT                NOT NULL,
+    -- Unix time
+    expires_at      INTEGER             NOT NULL
+);
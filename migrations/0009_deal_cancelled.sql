@@ -0,0 +1,2 @@
+-- Soft delete, cancelled deals are kept for the history but hidden from the bot
+ALTER TABLE deal ADD COLUMN cancelled_on DATETIME;
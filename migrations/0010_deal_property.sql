@@ -0,0 +1,7 @@
+-- Profitbase property details shown on the object card
+ALTER TABLE deal ADD COLUMN property_id BIGINTEGER;
+ALTER TABLE deal ADD COLUMN rooms INTEGER;
+ALTER TABLE deal ADD COLUMN floor INTEGER;
+ALTER TABLE deal ADD COLUMN section TEXT;
+ALTER TABLE deal ADD COLUMN area REAL;
+ALTER TABLE deal ADD COLUMN price REAL;
@@ -0,0 +1,11 @@
+-- Buyer contacts linked to the AmoCRM lead, replaced on every sync of the deal
+CREATE TABLE IF NOT EXISTS contact
+(
+    deal_id         BIGINTEGER          NOT NULL,
+    contact_id      BIGINTEGER          NOT NULL,
+    name            TEXT                NOT NULL,
+    phone           TEXT,
+    email           TEXT,
+    is_main         BOOLEAN             NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (deal_id, contact_id)
+);
@@ -0,0 +1,3 @@
+-- Parts of the Profitbase house name besides the house number
+ALTER TABLE deal ADD COLUMN corpus INTEGER;
+ALTER TABLE deal ADD COLUMN letter TEXT;
@@ -0,0 +1,3 @@
+-- Left behind by the teloxide SQLite dialogue storage. The inline menu carries its whole
+-- path in the callback data, so an open menu keeps working after a restart without it.
+DROP TABLE IF EXISTS teloxide_dialogues;
@@ -0,0 +1,6 @@
+-- Deals registered before handover tracking were handed over outside the bot.
+-- Left pending, they all come up as overdue in the first reminder.
+UPDATE deal SET handover_status = 'handed'
+WHERE handover_status = 'pending'
+  AND handover_date IS NULL
+  AND created_on < (SELECT installed_on FROM _sqlx_migrations WHERE version = 4);
@@ -0,0 +1,7 @@
+-- 0014 compared the sale date with the install time of 0004, so on a fresh upgrade
+-- it marked deals still within their handover period as handed. Put them back to pending,
+-- the admins set the status of old deals themselves. Handovers marked in the bot always have a date.
+UPDATE deal SET handover_status = 'pending'
+WHERE handover_status = 'handed'
+  AND handover_date IS NULL
+  AND handed_by IS NULL;
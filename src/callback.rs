@@ -0,0 +1,241 @@
+use crate::model::deal::Building;
+use crate::model::handover::HandoverStatus;
+use crate::model::user::Role;
+use crate::project::KEY_LEN;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Payload of inline keyboard buttons, Telegram allows up to 64 bytes.
+/// Projects and object types are referenced by `project::short_key` of their names,
+/// so old menus don't open another project after the registry is edited.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackData {
+    // -- Selection menu, edited in place
+    Menu,
+    Project {
+        project: String,
+    },
+    ObjectType {
+        project: String,
+        object_type: String,
+    },
+    House {
+        project: String,
+        object_type: String,
+        building: Building,
+        page: usize,
+    },
+    Object {
+        project: String,
+        object_type: String,
+        building: Building,
+        number: i32,
+        page: usize,
+    },
+    /// Page indicator button
+    Noop,
+    // -- Object card
+    /// Show the object card in a new message
+    Card { deal_id: u64 },
+    Handover { deal_id: u64, status: HandoverStatus },
+}
+
+impl CallbackData {
+    pub fn is_menu(&self) -> bool {
+        matches!(
+            self,
+            CallbackData::Menu
+                | CallbackData::Project { .. }
+                | CallbackData::ObjectType { .. }
+                | CallbackData::House { .. }
+                | CallbackData::Object { .. }
+        )
+    }
+
+    pub fn min_role(&self) -> Role {
+        match self {
+            CallbackData::Handover { .. } => Role::Manager,
+            _ => Role::Viewer,
+        }
+    }
+}
+
+impl Display for CallbackData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallbackData::Menu => write!(f, "menu"),
+            CallbackData::Project { project } => write!(f, "p:{}", project),
+            CallbackData::ObjectType {
+                project,
+                object_type,
+            } => write!(f, "t:{}:{}", project, object_type),
+            CallbackData::House {
+                project,
+                object_type,
+                building,
+                page,
+            } => write!(f, "h:{}:{}:{}:{}", project, object_type, building_key(building), page),
+            CallbackData::Object {
+                project,
+                object_type,
+                building,
+                number,
+                page,
+            } => write!(
+                f,
+                "o:{}:{}:{}:{}:{}",
+                project,
+                object_type,
+                building_key(building),
+                number,
+                page
+            ),
+            CallbackData::Noop => write!(f, "noop"),
+            CallbackData::Card { deal_id } => write!(f, "card:{}", deal_id),
+            CallbackData::Handover { deal_id, status } => {
+                write!(f, "handover:{}:{}", deal_id, status.as_str())
+            }
+        }
+    }
+}
+
+impl FromStr for CallbackData {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let tag = parts.next();
+        let mut next = || parts.next().ok_or(());
+        let res = match tag {
+            Some("menu") => CallbackData::Menu,
+            Some("p") => CallbackData::Project {
+                project: key(next()?)?,
+            },
+            Some("t") => CallbackData::ObjectType {
+                project: key(next()?)?,
+                object_type: key(next()?)?,
+            },
+            Some("h") => CallbackData::House {
+                project: key(next()?)?,
+                object_type: key(next()?)?,
+                building: building(next()?)?,
+                page: parse(next()?)?,
+            },
+            Some("o") => CallbackData::Object {
+                project: key(next()?)?,
+                object_type: key(next()?)?,
+                building: building(next()?)?,
+                number: parse(next()?)?,
+                page: parse(next()?)?,
+            },
+            Some("noop") => CallbackData::Noop,
+            Some("card") => CallbackData::Card {
+                deal_id: parse(next()?)?,
+            },
+            Some("handover") => CallbackData::Handover {
+                deal_id: parse(next()?)?,
+                status: next()?.parse()?,
+            },
+            _ => return Err(()),
+        };
+        if parts.next().is_some() {
+            return Err(());
+        }
+        Ok(res)
+    }
+}
+
+fn parse<T: FromStr>(s: &str) -> Result<T, ()> {
+    s.parse().map_err(|_| ())
+}
+
+/// Menus sent before keys were used carry registry indexes, those are rejected
+fn key(s: &str) -> Result<String, ()> {
+    if s.len() == KEY_LEN && s.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(s.to_string())
+    } else {
+        Err(())
+    }
+}
+
+/// "3..", "3.2." or "3.2.А": house, corpus and letter
+fn building_key(b: &Building) -> String {
+    format!(
+        "{}.{}.{}",
+        b.house,
+        b.corpus.map(|c| c.to_string()).unwrap_or_default(),
+        b.letter.as_deref().unwrap_or_default()
+    )
+}
+
+fn building(s: &str) -> Result<Building, ()> {
+    let mut parts = s.split('.');
+    let (Some(house), Some(corpus), Some(letter), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(());
+    };
+    Ok(Building {
+        house: parse(house)?,
+        corpus: (!corpus.is_empty()).then(|| parse(corpus)).transpose()?,
+        letter: (!letter.is_empty()).then(|| letter.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::short_key;
+
+    #[test]
+    fn round_trip() {
+        let cases = [
+            CallbackData::Menu,
+            CallbackData::Project {
+                project: short_key("ЖК Формат"),
+            },
+            CallbackData::ObjectType {
+                project: short_key("ЖК Формат"),
+                object_type: short_key("Квартиры"),
+            },
+            CallbackData::House {
+                project: short_key("ЖК Формат"),
+                object_type: short_key("Квартиры"),
+                building: Building {
+                    house: -1,
+                    ..Default::default()
+                },
+                page: 2,
+            },
+            CallbackData::Object {
+                project: short_key("ЖК Формат"),
+                object_type: short_key("Квартиры"),
+                building: Building {
+                    house: 12,
+                    corpus: Some(2),
+                    letter: Some("А".to_string()),
+                },
+                number: 345,
+                page: 2,
+            },
+            CallbackData::Noop,
+            CallbackData::Card { deal_id: 3001 },
+            CallbackData::Handover {
+                deal_id: 3001,
+                status: HandoverStatus::Disputed,
+            },
+        ];
+        for data in cases {
+            assert!(data.to_string().len() <= 64);
+            assert_eq!(data.to_string().parse::<CallbackData>(), Ok(data));
+        }
+        assert!("card:x".parse::<CallbackData>().is_err());
+        assert!("card:1:2".parse::<CallbackData>().is_err());
+        assert!("h:1:0:3".parse::<CallbackData>().is_err());
+        // Menus sent before corpus and letter were added
+        let house = format!("h:{}:{}:3:0", short_key("ЖК Формат"), short_key("Квартиры"));
+        assert!(house.parse::<CallbackData>().is_err());
+        // Registry indexes from menus sent by older versions
+        assert!("h:1:0:3:0".parse::<CallbackData>().is_err());
+        assert!("handover:1:lost".parse::<CallbackData>().is_err());
+    }
+}
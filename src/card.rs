@@ -0,0 +1,115 @@
+use crate::model::deal::HouseData;
+use serde::Deserialize;
+
+/// Profitbase details an object card can show, in template order
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CardField {
+    Property,
+    Rooms,
+    Floor,
+    Section,
+    Area,
+    Facing,
+    Price,
+}
+
+/// Used when the project has no template for the object type
+pub fn default_template(object_type: &str) -> Vec<CardField> {
+    use CardField::*;
+    match object_type {
+        "Квартиры" => vec![Rooms, Floor, Section, Area, Facing, Price],
+        _ => vec![Floor, Section, Area, Price],
+    }
+}
+
+/// One line per field, fields without a value are skipped
+pub fn render(template: &[CardField], b: &HouseData) -> String {
+    let mut out = String::new();
+    for field in template {
+        let line = match field {
+            CardField::Property => b.property_id.map(|id| format!("ID в Profitbase: {}", id)),
+            CardField::Rooms => b.rooms.map(|rooms| match rooms {
+                0 => "Комнат: студия".to_string(),
+                n => format!("Комнат: {}", n),
+            }),
+            CardField::Floor => b.floor.map(|floor| format!("Этаж: {}", floor)),
+            CardField::Section => b
+                .section
+                .as_ref()
+                .filter(|s| !s.is_empty())
+                .map(|s| format!("Подъезд/секция: {}", s)),
+            CardField::Area => b.area.map(|area| format!("Площадь: {} м²", area)),
+            CardField::Facing => Some(&b.facing)
+                .filter(|s| !s.is_empty())
+                .map(|s| format!("Тип отделки: {}", s)),
+            CardField::Price => b.price.map(|price| format!("Стоимость: {} ₽", group_digits(price))),
+        };
+        if let Some(line) = line {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// 5400000.0 -> "5 400 000"
+fn group_digits(value: f64) -> String {
+    let digits = (value.round() as i64).to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(' ');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::deal::DealForAdd;
+    use crate::model::{test_db, test_deal};
+
+    #[tokio::test]
+    async fn render_templates() {
+        let db = test_db().await;
+        let deal = DealForAdd {
+            rooms: Some(0),
+            floor: Some(5),
+            area: Some(41.5),
+            price: Some(5_400_000.0),
+            ..test_deal(3001)
+        };
+        db.create_deal(&deal).await.unwrap();
+        let deal = db.get_deal_by_id(3001).await.unwrap();
+
+        // No section and no facing, so those lines are left out
+        assert_eq!(
+            render(&default_template("Квартиры"), &deal),
+            "Комнат: студия\nЭтаж: 5\nПлощадь: 41.5 м²\nСтоимость: 5 400 000 ₽\n"
+        );
+        assert_eq!(
+            render(&default_template("Кладовки"), &deal),
+            "Этаж: 5\nПлощадь: 41.5 м²\nСтоимость: 5 400 000 ₽\n"
+        );
+        let custom = [CardField::Property, CardField::Price, CardField::Facing, CardField::Area];
+        assert_eq!(render(&custom, &deal), "Стоимость: 5 400 000 ₽\nПлощадь: 41.5 м²\n");
+        assert_eq!(render(&[CardField::Section], &deal), "");
+    }
+
+    #[test]
+    fn price() {
+        assert_eq!(group_digits(5_400_000.0), "5 400 000");
+        assert_eq!(group_digits(999.6), "1 000");
+        assert_eq!(group_digits(12.0), "12");
+    }
+
+    #[test]
+    fn template_parse() {
+        let fields: Vec<CardField> = serde_json::from_str(r#"["area", "price", "facing"]"#).unwrap();
+        assert_eq!(fields, vec![CardField::Area, CardField::Price, CardField::Facing]);
+        assert!(serde_json::from_str::<Vec<CardField>>(r#"["buyer"]"#).is_err());
+    }
+}
@@ -22,18 +22,20 @@ pub struct Config {
     pub TG_GROUP_ID: i64,
     // -- DB
     pub DB_URL: String,
-    // -- AmoCRM
-    pub AMO_CITY_URL: String,
-    pub AMO_CITY_TOKEN: String,
-    pub AMO_FORMAT_URL: String,
-    pub AMO_FORMAT_TOKEN: String,
-    // -- Profitbase
-    pub PROF_CITY_URL: String,
-    pub PROF_CITY_API_KEY: String,
-    pub PROF_FORMAT_URL: String,
-    pub PROF_FORMAT_API_KEY: String,
+    // -- Projects registry
+    pub PROJECTS_FILE: String,
+    /// The calendar shipped in holidays.txt when not set
+    pub HOLIDAYS_FILE: Option<String>,
     // -- Schedule for worker
     pub SCHEDULE: String,
+    /// Recheck of every stored deal against AmoCRM and Profitbase
+    pub REFRESH_SCHEDULE: String,
+    // -- Handover deadline reminders
+    pub REMINDER_SCHEDULE: String,
+    pub REMINDER_DAYS: u64,
+    // -- AmoCRM webhooks, the listener is off without them
+    pub WEBHOOK_ADDR: Option<String>,
+    pub WEBHOOK_SECRET: Option<String>,
 }
 
 impl Config {
@@ -42,15 +44,14 @@ impl Config {
             ADMIN_ID: get_env_as_parse("TG_HANMASTER_ID")?,
             TG_GROUP_ID: get_env_as_parse("TG_GROUP_ID")?,
             DB_URL: get_env("DB_URL")?,
-            AMO_CITY_URL: get_env("AMO_CITY_URL")?,
-            AMO_CITY_TOKEN: get_env("AMO_CITY_TOKEN")?,
-            AMO_FORMAT_URL: get_env("AMO_FORMAT_URL")?,
-            AMO_FORMAT_TOKEN: get_env("AMO_FORMAT_TOKEN")?,
-            PROF_CITY_URL: get_env("PROF_CITY_URL")?,
-            PROF_CITY_API_KEY: get_env("PROF_CITY_API_KEY")?,
-            PROF_FORMAT_URL: get_env("PROF_FORMAT_URL")?,
-            PROF_FORMAT_API_KEY: get_env("PROF_FORMAT_API_KEY")?,
+            PROJECTS_FILE: get_env("PROJECTS_FILE")?,
+            HOLIDAYS_FILE: get_env("HOLIDAYS_FILE").ok(),
             SCHEDULE: get_env("SCHEDULE")?,
+            REFRESH_SCHEDULE: get_env("REFRESH_SCHEDULE").unwrap_or_else(|_| "0 0 3 * * *".to_string()),
+            REMINDER_SCHEDULE: get_env("REMINDER_SCHEDULE").unwrap_or_else(|_| "0 0 9 * * *".to_string()),
+            REMINDER_DAYS: get_env_as_parse_or("REMINDER_DAYS", 3)?,
+            WEBHOOK_ADDR: get_env("WEBHOOK_ADDR").ok(),
+            WEBHOOK_SECRET: get_env("WEBHOOK_SECRET").ok(),
         })
     }
 }
@@ -63,3 +64,11 @@ fn get_env_as_parse<T: FromStr>(name: &'static str) -> Result<T> {
     let val = get_env(name)?;
     val.parse::<T>().map_err(|_| Error::ConfigWrongFormat(name))
 }
+
+/// A missing variable gets the default, a malformed one is still an error
+fn get_env_as_parse_or<T: FromStr>(name: &'static str, default: T) -> Result<T> {
+    match get_env(name) {
+        Ok(_) => get_env_as_parse(name),
+        Err(_) => Ok(default),
+    }
+}
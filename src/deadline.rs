@@ -0,0 +1,172 @@
+use crate::config::config;
+use crate::error::Error;
+use crate::Result;
+use chrono::{Datelike, Days, Weekday};
+use serde::Deserialize;
+use sqlx::types::chrono::NaiveDate;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::sync::OnceLock;
+
+/// Shipped with the bot, used when HOLIDAYS_FILE is not set
+const BUNDLED_CALENDAR: &str = include_str!("../holidays.txt");
+
+pub fn calendar() -> &'static Calendar {
+    static INSTANCE: OnceLock<Calendar> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| {
+        let calendar = match &config().HOLIDAYS_FILE {
+            Some(path) => Calendar::load_from_file(path),
+            None => Calendar::parse(BUNDLED_CALENDAR),
+        };
+        calendar.unwrap_or_else(|err| {
+            panic!("FATAL - WHILE LOADING Calendar -cause: {:?}", err);
+        })
+    })
+}
+
+/// Handover period from the contract terms
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Period {
+    pub days: u64,
+    #[serde(default)]
+    pub business_days: bool,
+}
+
+impl Default for Period {
+    fn default() -> Self {
+        Period {
+            days: 30,
+            business_days: false,
+        }
+    }
+}
+
+/// Production calendar: public holidays and weekends moved to working days
+#[derive(Debug, Default)]
+pub struct Calendar {
+    holidays: HashSet<NaiveDate>,
+    workdays: HashSet<NaiveDate>,
+    years: BTreeSet<i32>,
+}
+
+impl Calendar {
+    fn load_from_file(path: &str) -> Result<Calendar> {
+        let content = fs::read_to_string(path).map_err(Error::CalendarFileRead)?;
+        Calendar::parse(&content)
+    }
+
+    /// One `YYYY-MM-DD holiday|workday` per line, `#` starts a comment
+    fn parse(content: &str) -> Result<Calendar> {
+        let mut calendar = Calendar::default();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let date = parts
+                .next()
+                .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+            match (date, parts.next()) {
+                (Some(date), Some("holiday")) => calendar.holidays.insert(date),
+                (Some(date), Some("workday")) => calendar.workdays.insert(date),
+                _ => return Err(Error::CalendarFileParse(idx + 1)),
+            };
+            calendar.years.extend(date.map(|d| d.year()));
+        }
+        Ok(calendar)
+    }
+
+    /// Outside the loaded years only weekends are days off
+    pub fn has_year(&self, year: i32) -> bool {
+        self.years.contains(&year)
+    }
+
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        if self.workdays.contains(&date) {
+            return true;
+        }
+        if self.holidays.contains(&date) {
+            return false;
+        }
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+    }
+
+    fn next_business_day(&self, mut date: NaiveDate) -> NaiveDate {
+        while !self.is_business_day(date) {
+            date = date + Days::new(1);
+        }
+        date
+    }
+}
+
+/// Last day of the period counted from `start` (the start day itself is not counted).
+/// A calendar-day deadline that falls on a day off moves to the next business day.
+pub fn deadline(start: NaiveDate, period: Period, calendar: &Calendar) -> NaiveDate {
+    if !period.business_days {
+        return calendar.next_business_day(start + Days::new(period.days));
+    }
+
+    let mut date = start;
+    let mut left = period.days;
+    while left > 0 {
+        date = date + Days::new(1);
+        if calendar.is_business_day(date) {
+            left -= 1;
+        }
+    }
+    date
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn calendar() -> Calendar {
+        Calendar::parse(BUNDLED_CALENDAR).unwrap()
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            Calendar::parse("# comment\n2025-01-01 holiday\n2025-13-01 holiday"),
+            Err(Error::CalendarFileParse(3))
+        ));
+        assert!(Calendar::parse("2025-01-01 day-off").is_err());
+    }
+
+    #[test]
+    fn business_days() {
+        let calendar = calendar();
+        assert!(!calendar.is_business_day(date("2025-01-08")));
+        assert!(calendar.is_business_day(date("2025-01-09")));
+        assert!(!calendar.is_business_day(date("2025-03-15"))); // Saturday
+        assert!(calendar.is_business_day(date("2025-11-01"))); // working Saturday
+        assert!(!calendar.is_business_day(date("2027-05-03"))); // May 1 falls on Saturday
+        assert!(calendar.has_year(2027));
+        assert!(!calendar.has_year(2028));
+    }
+
+    #[test]
+    fn deadlines() {
+        let calendar = calendar();
+        let calendar_days = Period::default();
+        let business_days = Period {
+            days: 10,
+            business_days: true,
+        };
+        // Plain 30 days
+        assert_eq!(deadline(date("2025-03-12"), calendar_days, &calendar), date("2025-04-11"));
+        // 30 days end on Saturday 10.05.2025 and move to Monday
+        assert_eq!(deadline(date("2025-04-10"), calendar_days, &calendar), date("2025-05-12"));
+        // New year holidays are skipped
+        assert_eq!(deadline(date("2025-01-01"), business_days, &calendar), date("2025-01-22"));
+        // Working Saturday 01.11 counts, holidays 03.11-04.11 do not
+        assert_eq!(deadline(date("2025-10-27"), business_days, &calendar), date("2025-11-11"));
+    }
+}
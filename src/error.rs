@@ -9,12 +9,29 @@ pub enum Error {
     // -- Config
     ConfigMissingEnv(&'static str),
     ConfigWrongFormat(&'static str),
+    ProjectsFileRead(std::io::Error),
+    ProjectsFileParse(toml::de::Error),
+    /// Project name and the offending setting
+    ProjectsFileInvalid(String),
+    CalendarFileRead(std::io::Error),
+    /// Line number of the malformed entry
+    CalendarFileParse(usize),
 
     Sqlx(sqlx::Error),
+    Migrate(sqlx::migrate::MigrateError),
     Request(RequestError),
     RequestFailed(reqwest::Error),
+    AmoPageLimit(usize),
+    /// OAuth2 token could not be refreshed, needs a new authorization code
+    AmoAuthFailed(String),
+    /// Deal refers to a project missing from the registry
+    UnknownProject(String),
     ProfitAuthFailed,
     ProfitGetDataFailed,
+    /// Successful response without any property
+    ProfitEmptyData,
+    /// `houseName` without a building or section number
+    UnknownHouseName(String),
     Parse(ParseIntError),
 }
 
@@ -38,6 +55,12 @@ impl From<sqlx::Error> for Error {
     }
 }
 
+impl From<sqlx::migrate::MigrateError> for Error {
+    fn from(value: sqlx::migrate::MigrateError) -> Self {
+        Error::Migrate(value)
+    }
+}
+
 impl From<RequestError> for Error {
     fn from(value: RequestError) -> Self {
         Error::Request(value)
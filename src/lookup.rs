@@ -0,0 +1,60 @@
+/// Words allowed before the numbers, anything else is ordinary chat
+const LABELS: [&str; 9] = ["д", "дом", "кв", "квартира", "кл", "кладовка", "кладовая", "пом", "помещение"];
+
+/// House and object number from free text: "д3 кв57", "дом 3 кв. 57", "3/57", "3 57"
+pub fn parse_query(text: &str) -> Option<(i32, i32)> {
+    let text = text.trim().to_lowercase();
+    if let Some((house, number)) = text.split_once('/') {
+        return Some((house.trim().parse().ok()?, number.trim().parse().ok()?));
+    }
+
+    // The labels are optional, only the numbers matter
+    let mut numbers = vec![];
+    for token in text
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '.' || c == '№')
+        .filter(|t| !t.is_empty())
+    {
+        let label_end = token.find(|c: char| c.is_ascii_digit()).unwrap_or(token.len());
+        let (label, digits) = token.split_at(label_end);
+        if !label.is_empty() && !LABELS.contains(&label) {
+            return None;
+        }
+        if !digits.is_empty() {
+            numbers.push(digits.parse::<i32>().ok()?);
+        }
+    }
+
+    match numbers[..] {
+        [house, number] => Some((house, number)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("д3 кв57", Some((3, 57))),
+            ("Д3 КВ57", Some((3, 57))),
+            ("дом 3 кв. 57", Some((3, 57))),
+            ("д.3, кв.57", Some((3, 57))),
+            ("дом №3 кладовка №12", Some((3, 12))),
+            ("3/57", Some((3, 57))),
+            (" 3 / 57 ", Some((3, 57))),
+            ("3 57", Some((3, 57))),
+            ("57", None),
+            ("3/57/1", None),
+            ("д3 кв57 эт5", None),
+            ("привет", None),
+            ("3-57", None),
+            ("созвон в 10 на 15 минут", None),
+            ("эт 3 57", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_query(text), expected, "{}", text);
+        }
+    }
+}
@@ -1,51 +1,41 @@
+use crate::callback::CallbackData;
 use crate::config::config;
 pub use crate::error::Result;
-use crate::model::deal::{get_house_numbers, get_object_numbers, prepare_response};
-use crate::model::sync::sync;
+use crate::lookup::parse_query;
+use crate::menu::{contacts_visible, handover_kbd, menu_callback, send_menu, stale_callback};
+use crate::model::amo_auth::authorize;
+use crate::model::deal::prepare_response_by_id;
+use crate::model::handover::HandoverStatus;
+use crate::model::sync::{resync_deal, sync};
+use crate::model::sync_run::Trigger;
+use crate::model::user::Role;
+use crate::model::{init_db, Db};
+use crate::notify::notify;
+use crate::project::find_project;
 use dotenvy::dotenv;
-use log::info;
+use log::{error, info};
+use sqlx::types::chrono::{Local, NaiveDate};
 use std::error::Error;
-use teloxide::dispatching::dialogue;
-use teloxide::dispatching::dialogue::InMemStorage;
+use teloxide::dispatching::UpdateHandler;
 use teloxide::dptree::{case, deps};
-use teloxide::types::{KeyboardButton, KeyboardMarkup, KeyboardRemove, ReplyMarkup};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, User};
 use teloxide::{prelude::*, utils::command::BotCommands};
 
-type MyDialogue = Dialogue<State, InMemStorage<State>>;
 type HandlerResult = std::result::Result<(), Box<dyn Error + Send + Sync>>;
 
+mod callback;
+mod card;
 mod config;
+mod deadline;
 mod error;
+mod lookup;
+mod menu;
 mod model;
+mod notify;
+mod project;
+mod webhook;
 mod worker;
 
-#[derive(Clone, Default)]
-pub enum State {
-    #[default]
-    Start,
-    ChooseProject,
-    ChooseObjectType {
-        project: String,
-    },
-    ChooseHouseNumber {
-        project: String,
-        object_type: String,
-    },
-    ChooseObjectNumber {
-        project: String,
-        object_type: String,
-        house: i32,
-    },
-    ChooseAnswer {
-        project: String,
-        object_type: String,
-        house: i32,
-    },
-}
-
-const PROJECTS: [&str; 2] = ["DNS Сити", "ЖК Формат"];
-const OBJECT_TYPES: [&str; 2] = ["Квартиры", "Кладовки"];
-
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().expect("dotenv init failed");
@@ -58,40 +48,25 @@ async fn main() -> Result<()> {
         .await
         .expect("Failed to set bot commands");
 
+    let db = init_db().await?;
+
     let cloned_bot = bot.clone();
 
-    worker::do_work(cloned_bot);
+    worker::do_work(cloned_bot, db.clone());
+    webhook::serve(bot.clone(), db.clone());
 
-    let handler = dialogue::enter::<Update, InMemStorage<State>, State, _>()
+    let handler = dptree::entry()
+        .branch(invite_link_handler())
         .branch(
-            Update::filter_message()
-                .filter_command::<Command>()
-                .branch(case![Command::Sync].endpoint(sync_handler))
-                .branch(case![Command::Start].endpoint(start)),
+            dptree::filter_map_async(user_role)
+                .branch(callback_handler())
+                .branch(message_handler()),
         )
-        .branch(
-            Update::filter_message()
-                .branch(case![State::ChooseProject].endpoint(receive_project_name))
-                .branch(case![State::ChooseObjectType { project }].endpoint(receive_object_type))
-                .branch(
-                    case![State::ChooseHouseNumber {
-                        project,
-                        object_type
-                    }]
-                    .endpoint(receive_house_number),
-                )
-                .branch(
-                    case![State::ChooseObjectNumber {
-                        project,
-                        object_type,
-                        house,
-                    }]
-                    .endpoint(receive_object_number),
-                ),
-        );
+        .branch(Update::filter_callback_query().endpoint(deny_callback))
+        .branch(Update::filter_message().endpoint(deny_message));
 
     Dispatcher::builder(bot, handler)
-        .dependencies(deps![InMemStorage::<State>::new()])
+        .dependencies(deps![db])
         .enable_ctrlc_handler()
         .build()
         .dispatch()
@@ -100,239 +75,481 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// `/start <token>` from an invite link, the only update accepted from unknown users
+fn invite_link_handler() -> UpdateHandler<Box<dyn Error + Send + Sync + 'static>> {
+    Update::filter_message()
+        .filter_command::<Command>()
+        .branch(
+            case![Command::Start(token)]
+                .filter(|token: String| !token.is_empty())
+                .endpoint(accept_invite),
+        )
+}
+
+async fn user_role(upd: Update, db: Db) -> Option<Role> {
+    let user_id = upd.from()?.id.0 as i64;
+    if user_id == config().ADMIN_ID {
+        return Some(Role::Admin);
+    }
+    db.get_role(user_id).await.unwrap_or_else(|e| {
+        error!("[user_role] {:?}", e);
+        None
+    })
+}
+
+fn callback_handler() -> UpdateHandler<Box<dyn Error + Send + Sync + 'static>> {
+    Update::filter_callback_query()
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| q.data.and_then(|data| data.parse::<CallbackData>().ok()))
+                .branch(
+                    dptree::filter(|data: CallbackData, role: Role| role < data.min_role())
+                        .endpoint(forbidden_callback),
+                )
+                .branch(dptree::filter(|data: CallbackData| data.is_menu()).endpoint(menu_callback))
+                .branch(case![CallbackData::Card { deal_id }].endpoint(card_callback))
+                .branch(case![CallbackData::Handover { deal_id, status }].endpoint(handover_callback))
+                .branch(case![CallbackData::Noop].endpoint(noop_callback)),
+        )
+        // Buttons of menus sent by older versions carry a format that is no longer accepted
+        .endpoint(stale_callback)
+}
+
+fn message_handler() -> UpdateHandler<Box<dyn Error + Send + Sync + 'static>> {
+    Update::filter_message()
+        .branch(
+            dptree::entry()
+                .filter_command::<Command>()
+                .branch(
+                    dptree::filter(|cmd: Command, role: Role| role < cmd.min_role())
+                        .endpoint(forbidden_message),
+                )
+                .branch(case![Command::Sync(mode)].endpoint(sync_handler))
+                .branch(case![Command::Handed(args)].endpoint(handed_handler))
+                .branch(case![Command::Find(args)].endpoint(find_handler))
+                .branch(case![Command::Invite(role)].endpoint(invite_handler))
+                .branch(case![Command::Status].endpoint(status_handler))
+                .branch(case![Command::LastSync].endpoint(last_sync_handler))
+                .branch(case![Command::History(args)].endpoint(history_handler))
+                .branch(case![Command::Users(args)].endpoint(users_handler))
+                .branch(case![Command::Resync(args)].endpoint(resync_handler))
+                .branch(case![Command::AmoAuth(args)].endpoint(amo_auth_handler))
+                .branch(case![Command::Start(token)].endpoint(start)),
+        )
+        // In the group ordinary chat would be answered with "объект не найден"
+        .branch(dptree::filter(|msg: Message| msg.chat.is_private()).endpoint(receive_free_text))
+}
+
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase")]
 enum Command {
     /// Информация по объекту
-    Start,
-    /// Запрос данных в AmoCRM
-    Sync,
+    Start(String),
+    /// Запрос данных в AmoCRM (/sync full - полная пересинхронизация)
+    Sync(String),
+    /// Отметить передачу: /handed <id сделки> [дд.мм.гггг]
+    Handed(String),
+    /// Поиск объекта: /find <дом> <номер>
+    Find(String),
+    /// Пригласить пользователя: /invite <viewer|manager|admin>
+    Invite(String),
+    /// Расписание и количество сделок в базе
+    Status,
+    /// Результат последней синхронизации
+    LastSync,
+    /// История синхронизаций: /history [количество]
+    History(String),
+    /// Пользователи бота: /users [revoke <id>]
+    Users(String),
+    /// Перезагрузить сделку из Profitbase: /resync <id сделки>
+    Resync(String),
+    /// Авторизация AmoCRM: /amoauth <код авторизации> <проект>
+    AmoAuth(String),
 }
 
-fn make_kbd(step: i32) -> KeyboardMarkup {
-    let mut keyboard: Vec<Vec<KeyboardButton>> = vec![];
-
-    let labels = if step == 1 { PROJECTS } else { OBJECT_TYPES };
-
-    for label in labels.chunks(2) {
-        let row = label
-            .iter()
-            .map(|&item| KeyboardButton::new(item.to_owned()))
-            .collect();
-
-        keyboard.push(row);
+impl Command {
+    fn min_role(&self) -> Role {
+        match self {
+            Command::Sync(mode) if mode.trim() == "full" => Role::Admin,
+            Command::Invite(_)
+            | Command::Status
+            | Command::LastSync
+            | Command::History(_)
+            | Command::Users(_)
+            | Command::Resync(_)
+            | Command::AmoAuth(_) => Role::Admin,
+            Command::Sync(_) | Command::Handed(_) => Role::Manager,
+            Command::Start(_) | Command::Find(_) => Role::Viewer,
+        }
     }
-
-    KeyboardMarkup::new(keyboard).resize_keyboard()
 }
-async fn make_house_kbd(project: &str, object_type: &str) -> KeyboardMarkup {
-    let mut keyboard: Vec<Vec<KeyboardButton>> = vec![];
 
-    let labels = get_house_numbers(project, object_type).await;
+/// Object card with handover status buttons
+async fn send_card(bot: &Bot, chat_id: ChatId, card: Option<(String, u64)>) -> HandlerResult {
+    match card {
+        Some((text, deal_id)) => {
+            bot.send_message(chat_id, text)
+                .reply_markup(handover_kbd(deal_id))
+                .await?
+        }
+        None => bot.send_message(chat_id, "Сделка не найдена").await?,
+    };
+    Ok(())
+}
 
-    info!("LABELS {:?}", labels);
+fn staff_name(user: &User) -> String {
+    match &user.username {
+        Some(username) => format!("{} (@{})", user.full_name(), username),
+        None => user.full_name(),
+    }
+}
 
-    for label in labels.chunks(8) {
-        let row = label
-            .iter()
-            .map(|&item| KeyboardButton::new(item.to_string()))
-            .collect();
+async fn deny_callback(bot: Bot, q: CallbackQuery) -> HandlerResult {
+    bot.answer_callback_query(q.id).text("Нет доступа").await?;
+    Ok(())
+}
 
-        keyboard.push(row);
+/// Unknown users are answered in private chats only, the group stays quiet
+async fn deny_message(bot: Bot, msg: Message) -> HandlerResult {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "Нет доступа, обратитесь к администратору за приглашением")
+            .await?;
     }
+    Ok(())
+}
 
-    KeyboardMarkup::new(keyboard).resize_keyboard()
+async fn forbidden_callback(bot: Bot, q: CallbackQuery) -> HandlerResult {
+    bot.answer_callback_query(q.id).text("Недостаточно прав").await?;
+    Ok(())
 }
 
-async fn sync_handler(bot: Bot, msg: Message) -> HandlerResult {
-    let data_result = sync().await;
-    match data_result {
-        Ok(data) => {
-            bot.send_message(msg.chat.id, data.1).await?;
-        }
-        Err(e) => {
-            let admin_id = config().ADMIN_ID;
-            bot.send_message(ChatId(admin_id), e.to_string()).await?;
-            bot.send_message(msg.chat.id, e.to_string()).await?;
-        }
-    }
+async fn forbidden_message(bot: Bot, msg: Message) -> HandlerResult {
+    bot.send_message(msg.chat.id, "Недостаточно прав").await?;
     Ok(())
 }
 
-async fn start(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    if let Some(text) = msg.text() {
-        if text.starts_with("/start") {
-            let keyboard = make_kbd(1);
-            bot.send_message(msg.chat.id, "Выберите проект")
-                .reply_markup(keyboard)
-                .await?;
-            dialogue.update(State::ChooseProject).await?;
-        }
+async fn noop_callback(bot: Bot, q: CallbackQuery) -> HandlerResult {
+    bot.answer_callback_query(q.id).await?;
+    Ok(())
+}
+
+async fn card_callback(bot: Bot, db: Db, q: CallbackQuery, deal_id: u64, role: Role) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(message) = q.regular_message() {
+        let card = prepare_response_by_id(&db, deal_id, contacts_visible(role, &message.chat)).await?;
+        send_card(&bot, message.chat.id, card).await?;
     }
     Ok(())
 }
 
-async fn receive_project_name(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    match msg.text() {
-        Some(text) => {
-            if PROJECTS.contains(&text) {
-                if text.eq("ЖК Формат") {
-                    bot.send_message(msg.chat.id, "Нет данных")
-                        .reply_markup(ReplyMarkup::KeyboardRemove(KeyboardRemove::new()))
-                        .await?;
-                    dialogue.exit().await?;
-                } else {
-                    let keyboard = make_kbd(2);
-                    bot.send_message(msg.chat.id, "Квартиры или кладовки?")
-                        .reply_markup(keyboard)
-                        .await?;
-                    dialogue
-                        .update(State::ChooseObjectType {
-                            project: text.into(),
-                        })
-                        .await?;
-                }
-            } else {
-                bot.send_message(msg.chat.id, "Сделайте выбор кнопками")
+async fn handover_callback(
+    bot: Bot,
+    db: Db,
+    q: CallbackQuery,
+    (deal_id, status): (u64, HandoverStatus),
+    role: Role,
+) -> HandlerResult {
+    let deal = db.find_deal_by_id(deal_id).await?;
+    let Some(current) = deal.filter(|d| d.cancelled_on.is_none()).map(|d| d.handover_status) else {
+        // Cancelled or removed since the card was sent
+        bot.answer_callback_query(q.id)
+            .text("Сделка не найдена")
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    };
+    if current != status {
+        db.set_handover_status(deal_id, status, Local::now().naive_local(), &staff_name(&q.from))
+            .await?;
+        if let Some(message) = q.regular_message() {
+            // Keep the keyboard the card came with, it may have a "Back" button
+            let keyboard = message
+                .reply_markup()
+                .cloned()
+                .unwrap_or_else(|| handover_kbd(deal_id));
+            let contacts = contacts_visible(role, &message.chat);
+            if let Some((text, _)) = prepare_response_by_id(&db, deal_id, contacts).await? {
+                bot.edit_message_text(message.chat.id, message.id, text)
+                    .reply_markup(keyboard)
                     .await?;
             }
         }
+    }
+    bot.answer_callback_query(q.id)
+        .text(format!("Статус: {}", status.label()))
+        .await?;
+    Ok(())
+}
+
+async fn find_handler(bot: Bot, db: Db, msg: Message, args: String, role: Role) -> HandlerResult {
+    let contacts = contacts_visible(role, &msg.chat);
+    match parse_query(&args) {
+        Some((house, number)) => send_found(&bot, &db, msg.chat.id, house, number, contacts).await,
         None => {
-            bot.send_message(msg.chat.id, "Сделайте выбор кнопками")
+            bot.send_message(msg.chat.id, "Шаблон: /find <дом> <номер>, например /find 3 57")
                 .await?;
+            Ok(())
         }
     }
+}
 
+/// "д3 кв57" or "3/57" typed into a private chat
+async fn receive_free_text(bot: Bot, db: Db, msg: Message, role: Role) -> HandlerResult {
+    if let Some((house, number)) = msg.text().and_then(parse_query) {
+        let contacts = contacts_visible(role, &msg.chat);
+        send_found(&bot, &db, msg.chat.id, house, number, contacts).await?;
+    }
     Ok(())
 }
 
-async fn receive_object_type(
-    bot: Bot,
-    dialogue: MyDialogue,
-    project: String, // Available from `State::ChooseProject`.
-    msg: Message,
+async fn send_found(
+    bot: &Bot,
+    db: &Db,
+    chat_id: ChatId,
+    house: i32,
+    number: i32,
+    contacts: bool,
 ) -> HandlerResult {
-    match msg.text() {
-        Some(object_type) => {
-            if OBJECT_TYPES.contains(&object_type) {
-                let keyboard = make_house_kbd(&project, object_type).await;
-                bot.send_message(msg.chat.id, "Выберите номер дома")
-                    .reply_markup(keyboard)
-                    .await?;
-                dialogue
-                    .update(State::ChooseHouseNumber {
-                        project,
-                        object_type: object_type.into(),
-                    })
-                    .await?;
-            } else {
-                bot.send_message(msg.chat.id, "Сделайте выбор кнопками")
-                    .await?;
-            }
+    let deals = db.find_deals(house, number).await?;
+    match &deals[..] {
+        [] => {
+            bot.send_message(chat_id, format!("Дом № {} № {}: объект не найден", house, number))
+                .await?;
+        }
+        [deal] => {
+            let card = prepare_response_by_id(db, deal.deal_id, contacts).await?;
+            send_card(bot, chat_id, card).await?;
         }
         _ => {
-            bot.send_message(msg.chat.id, "Сделайте выбор кнопками")
+            let buttons = deals.iter().map(|d| {
+                vec![InlineKeyboardButton::callback(
+                    format!("{}, {} дом № {} № {}", d.project, d.object_type, d.building().label(), d.object),
+                    CallbackData::Card { deal_id: d.deal_id }.to_string(),
+                )]
+            });
+            bot.send_message(chat_id, "Найдено несколько объектов, уточните:")
+                .reply_markup(InlineKeyboardMarkup::new(buttons))
                 .await?;
         }
     }
+    Ok(())
+}
 
+async fn handed_handler(bot: Bot, db: Db, msg: Message, args: String, role: Role) -> HandlerResult {
+    let mut parts = args.split_whitespace();
+    let deal_id = parts.next().and_then(|id| id.parse::<u64>().ok());
+    let date = match parts.next() {
+        Some(date) => NaiveDate::parse_from_str(date, "%d.%m.%Y")
+            .ok()
+            .and_then(|d| d.and_hms_opt(12, 0, 0)),
+        None => Some(Local::now().naive_local()),
+    };
+    let (Some(deal_id), Some(date)) = (deal_id, date) else {
+        bot.send_message(msg.chat.id, "Шаблон: /handed <id сделки> [дд.мм.гггг]")
+            .await?;
+        return Ok(());
+    };
+
+    let by = msg.from.as_ref().map(staff_name).unwrap_or_default();
+    if db.set_handover_status(deal_id, HandoverStatus::Handed, date, &by).await? {
+        let card = prepare_response_by_id(&db, deal_id, contacts_visible(role, &msg.chat)).await?;
+        send_card(&bot, msg.chat.id, card).await?;
+    } else {
+        bot.send_message(msg.chat.id, "Сделка не найдена").await?;
+    }
     Ok(())
 }
 
-async fn receive_house_number(
-    bot: Bot,
-    dialogue: MyDialogue,
-    (project, object_type): (String, String), // Available from `State::ChooseObject`.
-    msg: Message,
-) -> HandlerResult {
-    match msg.text().map(|text| text.parse::<i32>()) {
-        Some(Ok(house)) => {
-            let houses = get_house_numbers(&project, &object_type).await;
-            if houses.contains(&house) {
-                let numbers = get_object_numbers(&project, &object_type, house).await;
-                if numbers.is_empty() {
-                    bot.send_message(msg.chat.id, "Объектов не найдено".to_string())
-                        .reply_markup(ReplyMarkup::KeyboardRemove(KeyboardRemove::new()))
-                        .await?;
-                } else {
-                    use std::fmt::Write;
-                    let message = numbers.iter().fold(
-                        "Найдены объекты с номерами:\n".to_string(),
-                        |mut output, b| {
-                            let _ = write!(output, "/{}, ", b);
-                            output
-                        },
-                    );
-                    bot.send_message(msg.chat.id, message)
-                        .reply_markup(ReplyMarkup::KeyboardRemove(KeyboardRemove::new()))
-                        .await?;
-                    if numbers.len() > 1 {
-                        bot.send_message(msg.chat.id, "Выберите номер помещения")
-                            .await?;
-                        dialogue
-                            .update(State::ChooseObjectNumber {
-                                project,
-                                object_type,
-                                house,
-                            })
-                            .await?;
-                    } else {
-                        let number = *numbers.first().unwrap();
-                        let report = prepare_response(&project, &object_type, house, number).await;
-                        bot.send_message(msg.chat.id, report).await?;
-                        bot.send_message(msg.chat.id, "Чтобы начать сначала,\n нажмите /start")
-                            .reply_markup(ReplyMarkup::KeyboardRemove(KeyboardRemove::new()))
-                            .await?;
-                        dialogue.exit().await?;
-                    }
-                };
-            } else {
-                bot.send_message(msg.chat.id, "Сделайте выбор кнопками")
-                    .await?;
+async fn sync_handler(bot: Bot, db: Db, msg: Message, mode: String) -> HandlerResult {
+    let full = mode.trim() == "full";
+    // user_role has already let through only messages with a sender
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let data_result = sync(&db, full, Trigger::User(user.id.0 as i64)).await;
+    match data_result {
+        Ok(report) => {
+            // A full resync can list hundreds of deals, longer than one message
+            for e in report.admin_notices() {
+                notify(&bot, ChatId(config().ADMIN_ID), &e).await;
+            }
+            notify(&bot, msg.chat.id, &report.to_string()).await;
+            if let Some(digest) = report.digest() {
+                notify(&bot, ChatId(config().TG_GROUP_ID), &digest).await;
             }
         }
-        _ => {
-            bot.send_message(msg.chat.id, "Сделайте выбор кнопками")
-                .await?;
+        Err(e) => {
+            let admin_id = config().ADMIN_ID;
+            bot.send_message(ChatId(admin_id), e.to_string()).await?;
+            bot.send_message(msg.chat.id, e.to_string()).await?;
         }
     }
+    Ok(())
+}
 
+async fn invite_handler(bot: Bot, db: Db, msg: Message, role: String) -> HandlerResult {
+    // The link is a credential, it must not be posted to the group
+    if !msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "Приглашение можно создать только в личном чате с ботом")
+            .await?;
+        return Ok(());
+    }
+    let (Ok(role), Some(user)) = (role.trim().parse::<Role>(), msg.from.as_ref()) else {
+        bot.send_message(msg.chat.id, "Шаблон: /invite <viewer|manager|admin>")
+            .await?;
+        return Ok(());
+    };
+    let token = db.create_invite(role, user.id.0 as i64).await?;
+    let me = bot.get_me().await?;
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Приглашение с ролью {} (одноразовое, действует 7 дней):\nhttps://t.me/{}?start={}",
+            role.as_str(),
+            me.username(),
+            token
+        ),
+    )
+    .await?;
     Ok(())
 }
 
-async fn receive_object_number(
-    bot: Bot,
-    dialogue: MyDialogue,
-    (project, object_type, house): (String, String, i32), // Available from `State::ChooseHouseNumber`.
-    msg: Message,
-) -> HandlerResult {
-    if let Some(text) = msg.text() {
-        let payload = text.trim_start_matches('/');
-        let payload = if payload.contains('@') {
-            payload.split('@').collect::<Vec<&str>>()[0]
-        } else {
-            payload
-        };
-        match payload.parse::<i32>() {
-            Ok(number) => {
-                let objects = get_object_numbers(&project, &object_type, house).await;
-                if objects.contains(&number) {
-                    let report = prepare_response(&project, &object_type, house, number).await;
-                    bot.send_message(msg.chat.id, report).await?;
-                    if objects.len() == 1 {
-                        bot.send_message(msg.chat.id, "Чтобы начать сначала,\n нажмите /start")
-                            .reply_markup(ReplyMarkup::KeyboardRemove(KeyboardRemove::new()))
-                            .await?;
-                        dialogue.exit().await?;
-                    }
-                }
-            }
+async fn status_handler(bot: Bot, db: Db, msg: Message) -> HandlerResult {
+    let mut text = String::new();
+    for (name, schedule) in [
+        ("Синхронизация", &config().SCHEDULE),
+        ("Сверка сделок", &config().REFRESH_SCHEDULE),
+        ("Напоминания", &config().REMINDER_SCHEDULE),
+    ] {
+        let next = worker::next_run(schedule)
+            .map_or("-".to_string(), |t| t.format("%d.%m.%Y %H:%M:%S").to_string());
+        text.push_str(&format!("{}: следующий запуск {}\n", name, next));
+    }
+    text.push_str("\nСделок в базе:\n");
+    for (project, count) in db.count_deals().await? {
+        text.push_str(&format!("{}: {}\n", project, count));
+    }
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn last_sync_handler(bot: Bot, db: Db, msg: Message) -> HandlerResult {
+    let text = match db.list_sync_runs(1).await?.first() {
+        Some(run) => run.to_string(),
+        None => "Синхронизация ещё не запускалась".to_string(),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn history_handler(bot: Bot, db: Db, msg: Message, args: String) -> HandlerResult {
+    let limit = match args.trim() {
+        "" => 10,
+        n => match n.parse::<u32>() {
+            Ok(n) if n > 0 => n.min(50),
             _ => {
-                bot.send_message(msg.chat.id, "Шаблон: /номер помещения")
-                    .await?;
+                bot.send_message(msg.chat.id, "Шаблон: /history [количество]").await?;
+                return Ok(());
+            }
+        },
+    };
+    let runs = db.list_sync_runs(limit).await?;
+    let text = if runs.is_empty() {
+        "Синхронизация ещё не запускалась".to_string()
+    } else {
+        runs.iter().map(|run| format!("{}\n", run)).collect()
+    };
+    notify(&bot, msg.chat.id, &text).await;
+    Ok(())
+}
+
+async fn users_handler(bot: Bot, db: Db, msg: Message, args: String) -> HandlerResult {
+    let mut parts = args.split_whitespace();
+    let text = match (parts.next(), parts.next().map(str::parse::<i64>)) {
+        (None, _) => {
+            let users = db.list_users().await?;
+            if users.is_empty() {
+                "Пользователей нет".to_string()
+            } else {
+                users
+                    .iter()
+                    .map(|u| format!("{} {} - {}\n", u.user_id, u.name, u.role.as_str()))
+                    .collect()
             }
         }
+        (Some("revoke"), Some(Ok(user_id))) => {
+            if db.revoke_user(user_id).await? {
+                format!("Доступ пользователя {} отозван", user_id)
+            } else {
+                "Пользователь не найден".to_string()
+            }
+        }
+        _ => "Шаблон: /users [revoke <id>]".to_string(),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn resync_handler(bot: Bot, db: Db, msg: Message, args: String, role: Role) -> HandlerResult {
+    let Ok(deal_id) = args.trim().parse::<u64>() else {
+        bot.send_message(msg.chat.id, "Шаблон: /resync <id сделки>").await?;
+        return Ok(());
+    };
+    if db.find_deal_by_id(deal_id).await?.is_none() {
+        bot.send_message(msg.chat.id, "Сделка не найдена").await?;
+        return Ok(());
     }
+    match resync_deal(&db, deal_id).await {
+        Ok(_) => {
+            let card = prepare_response_by_id(&db, deal_id, contacts_visible(role, &msg.chat)).await?;
+            send_card(&bot, msg.chat.id, card).await?;
+        }
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+        }
+    }
+    Ok(())
+}
 
+async fn amo_auth_handler(bot: Bot, db: Db, msg: Message, args: String) -> HandlerResult {
+    // The code grants access to the CRM, same as an invite link
+    if !msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "Код авторизации отправляйте только в личном чате с ботом")
+            .await?;
+        return Ok(());
+    }
+    let (code, project) = args.trim().split_once(' ').unwrap_or_default();
+    let Some(project) = find_project(project.trim()) else {
+        bot.send_message(msg.chat.id, "Шаблон: /amoauth <код авторизации> <проект>")
+            .await?;
+        return Ok(());
+    };
+    let text = match authorize(&db, project, code).await {
+        Ok(()) => format!("{}: AmoCRM авторизован", project.name),
+        Err(e) => format!("{}: {}", project.name, e),
+    };
+    bot.send_message(msg.chat.id, text).await?;
     Ok(())
 }
+
+async fn accept_invite(bot: Bot, db: Db, msg: Message, token: String) -> HandlerResult {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    match db.accept_invite(token.trim(), user.id.0 as i64, &staff_name(user)).await? {
+        Some(role) => {
+            info!("[accept_invite] {} joined as {}", staff_name(user), role.as_str());
+            bot.send_message(msg.chat.id, format!("Доступ открыт, роль: {}", role.as_str()))
+                .await?;
+            send_menu(&bot, &db, msg.chat.id).await
+        }
+        None => {
+            bot.send_message(msg.chat.id, "Приглашение недействительно или уже использовано")
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+async fn start(bot: Bot, db: Db, msg: Message) -> HandlerResult {
+    send_menu(&bot, &db, msg.chat.id).await
+}
@@ -0,0 +1,324 @@
+use crate::callback::CallbackData;
+use crate::model::deal::{prepare_response, Building};
+use crate::model::handover::HandoverStatus;
+use crate::model::user::Role;
+use crate::model::Db;
+use crate::project::{find_project_by_key, projects, short_key};
+use crate::{HandlerResult, Result};
+use teloxide::prelude::*;
+use teloxide::types::{Chat, InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Object numbers per page: 5 rows of 8 buttons
+const PAGE_SIZE: usize = 40;
+
+fn button(label: impl Into<String>, data: CallbackData) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(label, data.to_string())
+}
+
+fn back(data: CallbackData) -> Vec<InlineKeyboardButton> {
+    vec![button("« Назад", data)]
+}
+
+pub fn handover_kbd(deal_id: u64) -> InlineKeyboardMarkup {
+    let buttons: Vec<InlineKeyboardButton> = HandoverStatus::ALL
+        .iter()
+        .map(|status| {
+            button(
+                status.label(),
+                CallbackData::Handover {
+                    deal_id,
+                    status: *status,
+                },
+            )
+        })
+        .collect();
+
+    InlineKeyboardMarkup::new(buttons.chunks(2).map(|row| row.to_vec()))
+}
+
+/// Buyer contacts go to managers and admins in private chats, never to the group
+pub fn contacts_visible(role: Role, chat: &Chat) -> bool {
+    role >= Role::Manager && chat.is_private()
+}
+
+/// First step, sent by /start
+pub async fn send_menu(bot: &Bot, db: &Db, chat_id: ChatId) -> HandlerResult {
+    let (text, keyboard) = render(db, &CallbackData::Menu, false).await?;
+    bot.send_message(chat_id, text)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Every step edits the menu message in place
+pub async fn menu_callback(
+    bot: Bot,
+    db: Db,
+    q: CallbackQuery,
+    data: CallbackData,
+    role: Role,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(message) = q.regular_message() {
+        let contacts = contacts_visible(role, &message.chat);
+        let (text, keyboard) = match render(&db, &data, contacts).await {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                // Keep the message and its buttons, the user can tap again
+                bot.send_message(message.chat.id, "Ошибка чтения данных, попробуйте позже")
+                    .await?;
+                return Err(e.into());
+            }
+        };
+        bot.edit_message_text(message.chat.id, message.id, text)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+/// A button the bot can't parse, replace its menu with a way back to the start
+pub async fn stale_callback(bot: Bot, q: CallbackQuery) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).text("Меню устарело").await?;
+    if let Some(message) = q.regular_message() {
+        let (text, keyboard) = not_found();
+        bot.edit_message_text(message.chat.id, message.id, text)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn render(db: &Db, data: &CallbackData, contacts: bool) -> Result<(String, InlineKeyboardMarkup)> {
+    match data {
+        CallbackData::Project { project } => Ok(render_project(project)),
+        CallbackData::ObjectType {
+            project,
+            object_type,
+        } => render_object_type(db, project, object_type).await,
+        CallbackData::House {
+            project,
+            object_type,
+            building,
+            page,
+        } => render_house(db, project, object_type, building, *page, contacts).await,
+        CallbackData::Object {
+            project,
+            object_type,
+            building,
+            number,
+            page,
+        } => render_object(db, project, object_type, building, *number, *page, contacts).await,
+        _ => Ok(render_projects()),
+    }
+}
+
+/// Project and object type names by their keys, None after they were removed from the registry
+fn names(project: &str, object_type: &str) -> Option<(&'static str, &'static str)> {
+    let p = find_project_by_key(project)?;
+    let t = p.find_object_type(object_type)?;
+    Some((&p.name, t))
+}
+
+fn not_found() -> (String, InlineKeyboardMarkup) {
+    (
+        "Меню устарело, выберите проект заново".to_string(),
+        InlineKeyboardMarkup::new([back(CallbackData::Menu)]),
+    )
+}
+
+fn render_projects() -> (String, InlineKeyboardMarkup) {
+    let buttons: Vec<InlineKeyboardButton> = projects()
+        .iter()
+        .map(|p| {
+            button(
+                &p.name,
+                CallbackData::Project {
+                    project: short_key(&p.name),
+                },
+            )
+        })
+        .collect();
+
+    (
+        "Выберите проект".to_string(),
+        InlineKeyboardMarkup::new(buttons.chunks(2).map(|row| row.to_vec())),
+    )
+}
+
+fn render_project(project: &str) -> (String, InlineKeyboardMarkup) {
+    let Some(p) = find_project_by_key(project) else {
+        return not_found();
+    };
+    let buttons: Vec<InlineKeyboardButton> = p
+        .object_types
+        .iter()
+        .map(|t| {
+            button(
+                t,
+                CallbackData::ObjectType {
+                    project: project.to_string(),
+                    object_type: short_key(t),
+                },
+            )
+        })
+        .collect();
+
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> =
+        buttons.chunks(2).map(|row| row.to_vec()).collect();
+    keyboard.push(back(CallbackData::Menu));
+    (
+        format!("Проект: {}\nВыберите тип объекта", p.name),
+        InlineKeyboardMarkup::new(keyboard),
+    )
+}
+
+async fn render_object_type(
+    db: &Db,
+    project: &str,
+    object_type: &str,
+) -> Result<(String, InlineKeyboardMarkup)> {
+    let Some((project_name, type_name)) = names(project, object_type) else {
+        return Ok(not_found());
+    };
+    let houses = db.list_buildings(project_name, type_name).await?;
+    let buttons: Vec<InlineKeyboardButton> = houses
+        .iter()
+        .map(|building| {
+            button(
+                building.label(),
+                CallbackData::House {
+                    project: project.to_string(),
+                    object_type: object_type.to_string(),
+                    building: building.clone(),
+                    page: 0,
+                },
+            )
+        })
+        .collect();
+
+    // "3А, корпус 2" doesn't fit eight in a row
+    let per_row = if houses.iter().any(|b| b.corpus.is_some()) { 2 } else { 8 };
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> =
+        buttons.chunks(per_row).map(|row| row.to_vec()).collect();
+    keyboard.push(back(CallbackData::Project {
+        project: project.to_string(),
+    }));
+    let text = if houses.is_empty() {
+        format!("Проект: {}\n{}\nОбъектов не найдено", project_name, type_name)
+    } else {
+        format!("Проект: {}\n{}\nВыберите номер дома", project_name, type_name)
+    };
+    Ok((text, InlineKeyboardMarkup::new(keyboard)))
+}
+
+async fn render_house(
+    db: &Db,
+    project: &str,
+    object_type: &str,
+    building: &Building,
+    page: usize,
+    contacts: bool,
+) -> Result<(String, InlineKeyboardMarkup)> {
+    let Some((project_name, type_name)) = names(project, object_type) else {
+        return Ok(not_found());
+    };
+    let numbers = db.list_numbers(project_name, type_name, building).await?;
+    if let [number] = numbers[..] {
+        return render_object(db, project, object_type, building, number, 0, contacts).await;
+    }
+
+    let pages = numbers.len().div_ceil(PAGE_SIZE).max(1);
+    let page = page.min(pages - 1);
+    let buttons: Vec<InlineKeyboardButton> = numbers
+        .iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|&number| {
+            button(
+                number.to_string(),
+                CallbackData::Object {
+                    project: project.to_string(),
+                    object_type: object_type.to_string(),
+                    building: building.clone(),
+                    number,
+                    page,
+                },
+            )
+        })
+        .collect();
+
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> =
+        buttons.chunks(8).map(|row| row.to_vec()).collect();
+    if pages > 1 {
+        let to_page = |page| CallbackData::House {
+            project: project.to_string(),
+            object_type: object_type.to_string(),
+            building: building.clone(),
+            page,
+        };
+        let mut nav = vec![];
+        if page > 0 {
+            nav.push(button("«", to_page(page - 1)));
+        }
+        nav.push(button(format!("{}/{}", page + 1, pages), CallbackData::Noop));
+        if page + 1 < pages {
+            nav.push(button("»", to_page(page + 1)));
+        }
+        keyboard.push(nav);
+    }
+    keyboard.push(back(CallbackData::ObjectType {
+        project: project.to_string(),
+        object_type: object_type.to_string(),
+    }));
+
+    let text = if numbers.is_empty() {
+        format!("Проект: {}\n{}, дом № {}\nОбъектов не найдено", project_name, type_name, building.label())
+    } else {
+        format!(
+            "Проект: {}\n{}, дом № {}\nВыберите номер помещения",
+            project_name,
+            type_name,
+            building.label()
+        )
+    };
+    Ok((text, InlineKeyboardMarkup::new(keyboard)))
+}
+
+async fn render_object(
+    db: &Db,
+    project: &str,
+    object_type: &str,
+    building: &Building,
+    number: i32,
+    page: usize,
+    contacts: bool,
+) -> Result<(String, InlineKeyboardMarkup)> {
+    let Some((project_name, type_name)) = names(project, object_type) else {
+        return Ok(not_found());
+    };
+    let card = prepare_response(db, project_name, type_name, building, number, contacts).await?;
+
+    // A house with a single object skips the list, so go back to the houses
+    let single = db.list_numbers(project_name, type_name, building).await?.len() == 1;
+    let back_to = if single {
+        CallbackData::ObjectType {
+            project: project.to_string(),
+            object_type: object_type.to_string(),
+        }
+    } else {
+        CallbackData::House {
+            project: project.to_string(),
+            object_type: object_type.to_string(),
+            building: building.clone(),
+            page,
+        }
+    };
+
+    let (text, mut keyboard) = match card {
+        Some((text, deal_id)) => (text, handover_kbd(deal_id).inline_keyboard),
+        None => ("Объект не найден".to_string(), vec![]),
+    };
+    keyboard.push(back(back_to));
+    Ok((text, InlineKeyboardMarkup::new(keyboard)))
+}
@@ -0,0 +1,186 @@
+use crate::error::Error;
+use crate::model::data::{AmoContact, ContactRecord, Lead, Record};
+use crate::model::http::http;
+use crate::Result;
+use futures::stream::{self, Stream, TryStreamExt};
+use log::debug;
+use reqwest::StatusCode;
+
+/// Safeguard against a `next` link that never ends, 250 leads per page
+pub const MAX_PAGES: usize = 400;
+
+/// Streams leads page by page, following `_links.next.href` exactly as AmoCRM returns it.
+/// Ends on a missing `next`, a 204 response or a page without `_embedded`.
+pub fn lead_stream(url: String, token: &str, max_pages: usize) -> impl Stream<Item = Result<Lead>> {
+    let token = token.to_string();
+    stream::try_unfold((Some(url), 0), move |(url, page)| {
+        let token = token.clone();
+        async move {
+            let Some(url) = url else {
+                return Ok(None);
+            };
+            if page >= max_pages {
+                return Err(Error::AmoPageLimit(max_pages));
+            }
+
+            debug!("[lead_stream] page {}: {}", page + 1, url);
+            let response = http().send(http().get(&url).bearer_auth(&token)).await?;
+            if response.status() == StatusCode::NO_CONTENT {
+                return Ok(None);
+            }
+
+            let record = response.error_for_status()?.json::<Record>().await?;
+            let next = record._links.next.map(|l| l.href);
+            let leads = record._embedded.map(|e| e.leads).unwrap_or_default();
+            if leads.is_empty() {
+                return Ok(None);
+            }
+
+            Ok(Some((
+                stream::iter(leads.into_iter().map(Ok)),
+                (next, page + 1),
+            )))
+        }
+    })
+    .try_flatten()
+}
+
+/// Single lead with its contact ids, None when it was deleted
+pub async fn get_lead(amo_url: &str, token: &str, id: u64) -> Result<Option<Lead>> {
+    let url = format!("{}/api/v4/leads/{}?with=contacts", amo_url, id);
+    debug!("[get_lead] {}", url);
+    let response = http().send(http().get(&url).bearer_auth(token)).await?;
+    if matches!(response.status(), StatusCode::NO_CONTENT | StatusCode::NOT_FOUND) {
+        return Ok(None);
+    }
+    Ok(Some(response.error_for_status()?.json::<Lead>().await?))
+}
+
+/// Contacts by id in one request, deleted ones are missing from the result
+pub async fn get_contacts(amo_url: &str, token: &str, ids: &[u64]) -> Result<Vec<AmoContact>> {
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let filter: Vec<String> = ids.iter().map(|id| format!("filter[id][]={}", id)).collect();
+    let url = format!("{}/api/v4/contacts?limit=250&{}", amo_url, filter.join("&"));
+    debug!("[get_contacts] {}", url);
+    let response = http().send(http().get(&url).bearer_auth(token)).await?;
+    if response.status() == StatusCode::NO_CONTENT {
+        return Ok(vec![]);
+    }
+    let record = response.error_for_status()?.json::<ContactRecord>().await?;
+    Ok(record.embedded.map(|e| e.contacts).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn page(server: &MockServer, ids: &[u64], next: Option<&str>) -> serde_json::Value {
+        let leads: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                json!({
+                    "id": id,
+                    "name": format!("Сделка #{}", id),
+                    "created_at": 1741754280,
+                    "updated_at": 1741766400 + *id as i64,
+                    "pipeline_id": 7000001,
+                    "status_id": 58000001,
+                    "custom_fields_values": null
+                })
+            })
+            .collect();
+        let mut links = json!({ "self": { "href": format!("{}/api/v4/leads", server.uri()) } });
+        if let Some(next) = next {
+            links["next"] = json!({ "href": format!("{}{}", server.uri(), next) });
+        }
+        json!({ "_page": 1, "_links": links, "_embedded": { "leads": leads } })
+    }
+
+    async fn collect(server: &MockServer, max_pages: usize) -> Result<Vec<u64>> {
+        let url = format!("{}/api/v4/leads?limit=250", server.uri());
+        lead_stream(url, "token", max_pages)
+            .map_ok(|l| l.id)
+            .try_collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn follows_next_href() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/leads"))
+            .and(query_param("page", "2"))
+            .and(query_param("filter[updated_at][from]", "100"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(&server, &[3, 4], None)))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/leads"))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(
+                &server,
+                &[1, 2],
+                Some("/api/v4/leads?filter%5Bupdated_at%5D%5Bfrom%5D=100&limit=250&page=2"),
+            )))
+            .mount(&server)
+            .await;
+
+        assert_eq!(collect(&server, MAX_PAGES).await.unwrap(), vec![1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn stops_on_no_content() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(
+                &server,
+                &[1],
+                Some("/api/v4/leads?limit=250&page=2"),
+            )))
+            .mount(&server)
+            .await;
+
+        assert_eq!(collect(&server, MAX_PAGES).await.unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn stops_on_empty_embedded() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "_page": 1, "_links": {} })),
+            )
+            .mount(&server)
+            .await;
+
+        assert!(collect(&server, MAX_PAGES).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn page_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(
+                &server,
+                &[1],
+                Some("/api/v4/leads?limit=250&page=2"),
+            )))
+            .mount(&server)
+            .await;
+
+        assert!(matches!(
+            collect(&server, 3).await,
+            Err(Error::AmoPageLimit(3))
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::error::Error;
+use crate::model::http::http;
+use crate::model::Db;
+use crate::project::{AmoOAuth, Project};
+use crate::Result;
+use log::{debug, info};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::types::chrono::Utc;
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Seconds before `expires_at` when the stored AmoCRM token already counts as expired
+const EXPIRY_MARGIN: i64 = 60;
+
+#[derive(FromRow, Debug)]
+pub struct AmoToken {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    /// Seconds
+    expires_in: i64,
+}
+
+impl Db {
+    pub async fn get_amo_token(&self, project: &str) -> Result<Option<AmoToken>> {
+        let row = sqlx::query_as(
+            "SELECT access_token, refresh_token, expires_at FROM amo_token WHERE project = $1",
+        )
+        .bind(project)
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(row)
+    }
+
+    pub async fn save_amo_token(&self, project: &str, token: &AmoToken) -> Result<()> {
+        debug!("[save_amo_token] {} expires at {}", project, token.expires_at);
+        sqlx::query(
+            r#"
+            INSERT INTO amo_token (project, access_token, refresh_token, expires_at) VALUES ($1, $2, $3, $4)
+            ON CONFLICT(project) DO UPDATE SET
+                access_token = excluded.access_token,
+                refresh_token = excluded.refresh_token,
+                expires_at = excluded.expires_at"#,
+        )
+        .bind(project)
+        .bind(&token.access_token)
+        .bind(&token.refresh_token)
+        .bind(token.expires_at)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Bearer token for AmoCRM API calls, refreshed and stored when it is about to expire
+pub async fn amo_access_token(db: &Db, project: &Project) -> Result<String> {
+    access_token(db, project, false).await
+}
+
+/// Refreshes even an unexpired token, for a 401 after it was revoked in AmoCRM
+pub async fn refresh_amo_token(db: &Db, project: &Project) -> Result<String> {
+    access_token(db, project, true).await
+}
+
+/// AmoCRM rejected the token, only worth a retry with OAuth configured
+pub fn is_unauthorized(project: &Project, e: &Error) -> bool {
+    project.oauth.is_some()
+        && matches!(e, Error::RequestFailed(e) if e.status() == Some(StatusCode::UNAUTHORIZED))
+}
+
+/// Serializes refreshes of a project, two concurrent ones would spend the same refresh token
+fn token_lock(project: &str) -> Arc<tokio::sync::Mutex<()>> {
+    static LOCKS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+
+    let mut locks = LOCKS.get_or_init(Default::default).lock().unwrap();
+    locks.entry(project.to_string()).or_default().clone()
+}
+
+async fn access_token(db: &Db, project: &Project, force: bool) -> Result<String> {
+    let Some(oauth) = &project.oauth else {
+        return Ok(project.amo_token.clone());
+    };
+    let lock = token_lock(&project.name);
+    let _guard = lock.lock().await;
+
+    let stored = db.get_amo_token(&project.name).await?;
+    if let Some(token) = &stored
+        && !force
+        && token.expires_at - EXPIRY_MARGIN > Utc::now().timestamp()
+    {
+        return Ok(token.access_token.clone());
+    }
+
+    // Refresh tokens are single-use, the stored one always wins over the config
+    let refresh_token = stored
+        .map(|t| t.refresh_token)
+        .or_else(|| oauth.refresh_token.clone())
+        .ok_or_else(|| Error::AmoAuthFailed("нет refresh token".to_string()))?;
+    let token = request_token(project, oauth, "refresh_token", &refresh_token).await?;
+    db.save_amo_token(&project.name, &token).await?;
+    info!("[amo_access_token] {} token refreshed", project.name);
+    Ok(token.access_token)
+}
+
+/// First authorization with a code from the integration settings
+pub async fn authorize(db: &Db, project: &Project, code: &str) -> Result<()> {
+    let oauth = project
+        .oauth
+        .as_ref()
+        .ok_or_else(|| Error::AmoAuthFailed("OAuth не настроен для проекта".to_string()))?;
+    let lock = token_lock(&project.name);
+    let _guard = lock.lock().await;
+    let token = request_token(project, oauth, "authorization_code", code).await?;
+    db.save_amo_token(&project.name, &token).await
+}
+
+async fn request_token(project: &Project, oauth: &AmoOAuth, grant_type: &str, secret: &str) -> Result<AmoToken> {
+    let secret_field = match grant_type {
+        "authorization_code" => "code",
+        _ => "refresh_token",
+    };
+    let mut payload = json!({
+        "client_id": oauth.client_id,
+        "client_secret": oauth.client_secret,
+        "grant_type": grant_type,
+        "redirect_uri": oauth.redirect_uri,
+    });
+    payload[secret_field] = json!(secret);
+
+    let url = format!("{}/oauth2/access_token", project.amo_url);
+    // The refresh token is single-use, a repeated request could lose the new pair
+    let response = http().send_once(http().post(&url).json(&payload)).await?;
+    if response.status() != StatusCode::OK {
+        return Err(Error::AmoAuthFailed(format!("HTTP {}", response.status())));
+    }
+    let data = response.json::<TokenResponse>().await?;
+    Ok(AmoToken {
+        access_token: data.access_token,
+        refresh_token: data.refresh_token,
+        expires_at: Utc::now().timestamp() + data.expires_in,
+    })
+}
+
+/// What the admin has to do when the refresh token is gone or revoked
+pub fn reauth_instructions(project: &str) -> String {
+    format!(
+        "Не удалось обновить токен AmoCRM. Синхронизация проекта остановлена.\n\
+        1. Откройте в AmoCRM: Настройки → Интеграции → интеграция бота → Ключи и доступы.\n\
+        2. Скопируйте код авторизации (действует 20 минут).\n\
+        3. Отправьте боту в личном чате: /amoauth <код> {}",
+        project
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_db;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn oauth_project(name: &str, amo_url: &str) -> Project {
+        toml::from_str(&format!(
+            r#"
+            name = "{}"
+            amo_url = "{}"
+            prof_url = ""
+            prof_api_key = ""
+            object_types = []
+            filter = {{ field_id = 0 }}
+            oauth = {{ client_id = "id", client_secret = "secret", redirect_uri = "https://example.com", refresh_token = "r1" }}
+            "#,
+            name, amo_url
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rotates_refresh_token() {
+        let db = test_db().await;
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth2/access_token"))
+            .and(body_partial_json(json!({ "grant_type": "refresh_token", "refresh_token": "r1" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "token_type": "Bearer", "expires_in": 86400, "access_token": "a2", "refresh_token": "r2"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth2/access_token"))
+            .and(body_partial_json(json!({ "refresh_token": "r2" })))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let project = oauth_project("ЖК Формат", &server.uri());
+
+        // The first call refreshes with the configured token, the second one reuses the stored access token
+        assert_eq!(amo_access_token(&db, &project).await.unwrap(), "a2");
+        assert_eq!(amo_access_token(&db, &project).await.unwrap(), "a2");
+
+        // Forced after a 401: the rotated refresh token is used and rejected
+        let token = db.get_amo_token("ЖК Формат").await.unwrap().unwrap();
+        assert_eq!(token.refresh_token, "r2");
+        assert!(matches!(
+            refresh_amo_token(&db, &project).await,
+            Err(Error::AmoAuthFailed(_))
+        ));
+    }
+    #[tokio::test]
+    async fn refreshes_once_for_concurrent_calls() {
+        let db = test_db().await;
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth2/access_token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({
+                        "token_type": "Bearer", "expires_in": 86400, "access_token": "a2", "refresh_token": "r2"
+                    }))
+                    .set_delay(std::time::Duration::from_millis(100)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // The second call waits for the first refresh and reuses its token
+        let project = oauth_project("DNS Сити", &server.uri());
+        let (first, second) = tokio::join!(amo_access_token(&db, &project), amo_access_token(&db, &project));
+        assert_eq!(first.unwrap(), "a2");
+        assert_eq!(second.unwrap(), "a2");
+    }
+}
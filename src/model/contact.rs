@@ -0,0 +1,163 @@
+use crate::model::amo::get_contacts;
+use crate::model::data::{AmoContact, ContactRef, CustomField, FlexibleType};
+use crate::model::Db;
+use crate::Result;
+use log::debug;
+use sqlx::FromRow;
+use std::fmt::Write;
+
+/// Buyer contact from AmoCRM, shown on the card to managers and admins only
+#[derive(FromRow, Debug, Clone, PartialEq)]
+pub struct Contact {
+    pub contact_id: i64,
+    pub name: String,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub is_main: bool,
+}
+
+impl Contact {
+    fn from_amo(contact: AmoContact, is_main: bool) -> Self {
+        let fields = contact.custom_fields_values.unwrap_or_default();
+        Contact {
+            contact_id: contact.id as i64,
+            name: contact.name,
+            phone: field_values(&fields, "PHONE"),
+            email: field_values(&fields, "EMAIL"),
+            is_main,
+        }
+    }
+}
+
+/// All values of the predefined field joined, a contact often has several phones
+fn field_values(fields: &[CustomField], code: &str) -> Option<String> {
+    let values: Vec<String> = fields
+        .iter()
+        .filter(|f| f.field_code.as_deref() == Some(code))
+        .flat_map(|f| &f.values)
+        .map(|v| match &v.value {
+            FlexibleType::Str(s) => s.clone(),
+            FlexibleType::Int(i) => i.to_string(),
+        })
+        .filter(|v| !v.is_empty())
+        .collect();
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// Contacts linked to the lead, the main one first. Deleted contacts are skipped.
+pub async fn fetch_contacts(amo_url: &str, token: &str, refs: &[ContactRef]) -> Result<Vec<Contact>> {
+    let ids: Vec<u64> = refs.iter().map(|r| r.id).collect();
+    let mut contacts: Vec<Contact> = get_contacts(amo_url, token, &ids)
+        .await?
+        .into_iter()
+        .map(|contact| {
+            let is_main = refs.iter().any(|r| r.id == contact.id && r.is_main);
+            Contact::from_amo(contact, is_main)
+        })
+        .collect();
+    contacts.sort_by_key(|c| !c.is_main);
+    Ok(contacts)
+}
+
+impl Db {
+    /// Replaces whatever was stored for the deal
+    pub async fn save_contacts(&self, deal_id: u64, contacts: &[Contact]) -> Result<()> {
+        debug!("[save_contacts] deal {}: {} contacts", deal_id, contacts.len());
+        let mut tx = self.db.begin().await?;
+        sqlx::query("DELETE FROM contact WHERE deal_id = $1")
+            .bind(deal_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        for c in contacts {
+            sqlx::query(
+                r#"
+                INSERT INTO contact (deal_id, contact_id, name, phone, email, is_main)
+                VALUES ($1, $2, $3, $4, $5, $6)"#,
+            )
+            .bind(deal_id as i64)
+            .bind(c.contact_id)
+            .bind(&c.name)
+            .bind(&c.phone)
+            .bind(&c.email)
+            .bind(c.is_main)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn list_contacts(&self, deal_id: u64) -> Result<Vec<Contact>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT contact_id, name, phone, email, is_main FROM contact
+            WHERE deal_id = $1 ORDER BY is_main DESC, contact_id"#,
+        )
+        .bind(deal_id as i64)
+        .fetch_all(&self.db)
+        .await?;
+        Ok(rows)
+    }
+}
+
+pub fn format_contacts(contacts: &[Contact]) -> String {
+    let mut res = String::new();
+    for c in contacts {
+        let label = if c.is_main { "Покупатель" } else { "Контакт" };
+        let details: Vec<&str> = [Some(c.name.as_str()), c.phone.as_deref(), c.email.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter(|s| !s.is_empty())
+            .collect();
+        let _ = writeln!(res, "{}: {}", label, details.join(", "));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_db;
+    use serde_json::json;
+
+    #[test]
+    fn phone_and_email() {
+        let contact: AmoContact = serde_json::from_value(json!({
+            "id": 501, "name": "Иванов Иван",
+            "custom_fields_values": [
+                { "field_id": 1, "field_name": "Телефон", "field_code": "PHONE", "values": [
+                    { "value": "+79000000001", "enum_id": 1 }, { "value": "+79000000002", "enum_id": 2 }
+                ] },
+                { "field_id": 2, "field_name": "Email", "field_code": "EMAIL", "values": [{ "value": "ivan@example.com" }] },
+                { "field_id": 3, "field_name": "Должность", "values": [{ "value": "Директор" }] }
+            ]
+        }))
+        .unwrap();
+        let contact = Contact::from_amo(contact, true);
+        assert_eq!(contact.phone.as_deref(), Some("+79000000001, +79000000002"));
+        assert_eq!(contact.email.as_deref(), Some("ivan@example.com"));
+        assert_eq!(
+            format_contacts(&[contact]),
+            "Покупатель: Иванов Иван, +79000000001, +79000000002, ivan@example.com\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn save_replaces_contacts() {
+        let db = test_db().await;
+        let contact = |contact_id, is_main| Contact {
+            contact_id,
+            name: format!("Контакт {}", contact_id),
+            phone: None,
+            email: None,
+            is_main,
+        };
+        db.save_contacts(3001, &[contact(1, false), contact(2, true)]).await.unwrap();
+        let ids = |list: Vec<Contact>| list.iter().map(|c| c.contact_id).collect::<Vec<_>>();
+        assert_eq!(ids(db.list_contacts(3001).await.unwrap()), vec![2, 1]);
+
+        db.save_contacts(3001, &[contact(3, true)]).await.unwrap();
+        assert_eq!(ids(db.list_contacts(3001).await.unwrap()), vec![3]);
+        assert!(db.list_contacts(3002).await.unwrap().is_empty());
+    }
+}
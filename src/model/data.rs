@@ -2,11 +2,12 @@ use serde::Deserialize;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Record {
+    #[serde(default)]
     pub _links: Links,
-    pub _embedded: Embedded,
+    pub _embedded: Option<Embedded>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Links {
     pub next: Option<Link>,
 }
@@ -18,6 +19,7 @@ pub struct Link {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Embedded {
+    #[serde(default)]
     pub leads: Vec<Lead>,
 }
 
@@ -27,12 +29,54 @@ pub struct Lead {
     pub id: u64,
     pub name: String,
     pub created_at: i64,
-    pub custom_fields_values: Vec<CustomField>,
+    pub updated_at: i64,
+    pub pipeline_id: u64,
+    pub status_id: u64,
+    pub custom_fields_values: Option<Vec<CustomField>>,
+    /// Filled only when requested with `with=contacts`
+    #[serde(default, rename = "_embedded")]
+    pub embedded: LeadEmbedded,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LeadEmbedded {
+    #[serde(default)]
+    pub contacts: Vec<ContactRef>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ContactRef {
+    pub id: u64,
+    #[serde(default)]
+    pub is_main: bool,
+}
+
+/// Page of `/api/v4/contacts`
+#[derive(Deserialize, Debug, Clone)]
+pub struct ContactRecord {
+    #[serde(rename = "_embedded")]
+    pub embedded: Option<ContactsEmbedded>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ContactsEmbedded {
+    #[serde(default)]
+    pub contacts: Vec<AmoContact>,
 }
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AmoContact {
+    pub id: u64,
+    pub name: String,
+    pub custom_fields_values: Option<Vec<CustomField>>,
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct CustomField {
     pub field_id: u64,
     pub field_name: String,
+    /// PHONE, EMAIL and the like for the predefined fields
+    pub field_code: Option<String>,
     pub values: Vec<Val>,
 }
 
@@ -56,9 +100,18 @@ pub struct ProfitRecord {
     pub data: Vec<ProfitData>,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug, Clone)]
 pub struct ProfitData {
+    /// Property id in Profitbase
+    pub id: Option<i64>,
     pub number: String,
+    pub rooms_amount: Option<i32>,
+    pub floor: Option<i32>,
+    #[serde(rename = "sectionName")]
+    pub section_name: Option<String>,
+    pub area: Option<ProfitArea>,
+    pub price: Option<ProfitPrice>,
     #[serde(rename = "houseName")]
     pub house_name: String,
     #[serde(rename = "projectName")]
@@ -68,6 +121,16 @@ pub struct ProfitData {
     pub sold_at: String,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProfitArea {
+    pub area_total: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProfitPrice {
+    pub value: Option<f64>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Attrs {
     pub facing: Option<String>,
@@ -1,10 +1,13 @@
+use crate::card::{self, default_template};
+use crate::deadline::{self, calendar, Period};
+use crate::model::contact::format_contacts;
+use crate::model::handover::HandoverStatus;
 use crate::model::Db;
+use crate::project::find_project;
 use crate::Result;
-use log::{debug, error};
-use sqlx::types::chrono::NaiveDateTime;
+use log::debug;
+use sqlx::types::chrono::{NaiveDate, NaiveDateTime};
 use sqlx::FromRow;
-use std::ops::Add;
-use std::time::Duration;
 
 #[allow(dead_code)]
 #[derive(FromRow)]
@@ -18,17 +21,43 @@ pub struct HouseData {
     pub facing: String,
     pub created_on: NaiveDateTime,
     pub updated_on: String,
+    pub handover_status: HandoverStatus,
+    pub handover_date: Option<NaiveDateTime>,
+    pub handed_by: Option<String>,
+    pub cancelled_on: Option<NaiveDateTime>,
+    pub property_id: Option<i64>,
+    pub rooms: Option<i32>,
+    pub floor: Option<i32>,
+    pub section: Option<String>,
+    pub area: Option<f64>,
+    pub price: Option<f64>,
+    pub corpus: Option<i32>,
+    pub letter: Option<String>,
 }
-#[derive(FromRow, Debug)]
-pub struct HouseNumbers {
+/// House number with its corpus and letter, "3", "3А" and "3, корпус 2" are different buildings
+#[derive(FromRow, Debug, Clone, Default, PartialEq)]
+pub struct Building {
     pub house: i32,
+    pub corpus: Option<i32>,
+    pub letter: Option<String>,
+}
+
+impl Building {
+    /// "3", "3А" or "3А, корпус 2"
+    pub fn label(&self) -> String {
+        let mut label = format!("{}{}", self.house, self.letter.as_deref().unwrap_or_default());
+        if let Some(corpus) = self.corpus {
+            label.push_str(&format!(", корпус {}", corpus));
+        }
+        label
+    }
 }
 #[derive(FromRow, Debug)]
 pub struct ObjectNumbers {
     pub object: i32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DealForAdd {
     pub deal_id: u64,
     pub project: String,
@@ -37,34 +66,69 @@ pub struct DealForAdd {
     pub object: i32,
     pub facing: String,
     pub created_on: NaiveDateTime,
+    pub property_id: Option<i64>,
+    pub rooms: Option<i32>,
+    pub floor: Option<i32>,
+    pub section: Option<String>,
+    pub area: Option<f64>,
+    pub price: Option<f64>,
+    pub corpus: Option<i32>,
+    pub letter: Option<String>,
+}
+
+impl HouseData {
+    pub fn building(&self) -> Building {
+        Building {
+            house: self.house,
+            corpus: self.corpus,
+            letter: self.letter.clone(),
+        }
+    }
+}
+
+impl DealForAdd {
+    pub fn building(&self) -> Building {
+        Building {
+            house: self.house,
+            corpus: self.corpus,
+            letter: self.letter.clone(),
+        }
+    }
 }
 
 impl Db {
-    pub async fn list_house_numbers(&self, project: &str, object_type: &str) -> Result<Vec<i32>> {
-        let records: Vec<HouseNumbers> = sqlx::query_as(
-            "SELECT DISTINCT house FROM deal WHERE project = $1 AND object_type = $2 ORDER BY house ",
+    pub async fn list_buildings(&self, project: &str, object_type: &str) -> Result<Vec<Building>> {
+        let records: Vec<Building> = sqlx::query_as(
+            r#"
+            SELECT DISTINCT house, corpus, letter FROM deal
+            WHERE project = $1 AND object_type = $2 AND cancelled_on IS NULL ORDER BY house, corpus, letter"#,
         )
         .bind(project)
         .bind(object_type)
         .fetch_all(&self.db)
         .await?;
-        debug!("[list_house_numbers] {:#?}", records);
-        let res = records.iter().map(|r| r.house).collect();
-        Ok(res)
+        debug!("[list_buildings] {:#?}", records);
+        Ok(records)
     }
 
+    /// `IS` so that a missing corpus or letter matches NULL
     pub async fn list_numbers(
         &self,
         project: &str,
         object_type: &str,
-        house: i32,
+        building: &Building,
     ) -> Result<Vec<i32>> {
         let records: Vec<ObjectNumbers> = sqlx::query_as(
-            "SELECT object FROM deal WHERE project = $1 AND object_type = $2 AND house = $3 ORDER BY object ",
+            r#"
+            SELECT object FROM deal
+            WHERE project = $1 AND object_type = $2 AND house = $3 AND corpus IS $4 AND letter IS $5
+                AND cancelled_on IS NULL ORDER BY object"#,
         )
         .bind(project)
         .bind(object_type)
-            .bind(house)
+        .bind(building.house)
+        .bind(building.corpus)
+        .bind(&building.letter)
         .fetch_all(&self.db)
         .await?;
         let res = records.iter().map(|r| r.object).collect();
@@ -75,22 +139,90 @@ impl Db {
         debug!("create deal with data: {:?}", &d);
         let (id,): (i64,) = sqlx::query_as(
             r#"
-                INSERT INTO deal (deal_id, project, house, object_type, object, facing, created_on)
-                VALUES($1, $2, $3, $4, $5, $6,$7) returning id"#,
+                INSERT INTO deal (deal_id, project, house, object_type, object, facing, created_on,
+                    property_id, rooms, floor, section, area, price, corpus, letter)
+                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) returning id"#,
         )
-        .bind(d.deal_id as i32)
+        .bind(d.deal_id as i64)
         .bind(&d.project)
         .bind(d.house)
         .bind(&d.object_type)
         .bind(d.object)
         .bind(&d.facing)
         .bind(d.created_on)
+        .bind(d.property_id)
+        .bind(d.rooms)
+        .bind(d.floor)
+        .bind(&d.section)
+        .bind(d.area)
+        .bind(d.price)
+        .bind(d.corpus)
+        .bind(&d.letter)
         .fetch_one(&self.db)
         .await?;
         debug!("Created row with id: {}", id);
         Ok(())
     }
 
+    /// Overwrites the object data, handover status is kept
+    pub async fn update_deal(&self, d: &DealForAdd) -> Result<bool> {
+        debug!("update deal with data: {:?}", &d);
+        let result = sqlx::query(
+            r#"
+            UPDATE deal SET project = $1, house = $2, object_type = $3, object = $4, facing = $5,
+                created_on = $6, property_id = $7, rooms = $8, floor = $9, section = $10, area = $11,
+                price = $12, corpus = $13, letter = $14, updated_on = datetime('now', 'localtime')
+            WHERE deal_id = $15"#,
+        )
+        .bind(&d.project)
+        .bind(d.house)
+        .bind(&d.object_type)
+        .bind(d.object)
+        .bind(&d.facing)
+        .bind(d.created_on)
+        .bind(d.property_id)
+        .bind(d.rooms)
+        .bind(d.floor)
+        .bind(&d.section)
+        .bind(d.area)
+        .bind(d.price)
+        .bind(d.corpus)
+        .bind(&d.letter)
+        .bind(d.deal_id as i64)
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Hides the deal from the bot, returns false when it was cancelled already
+    pub async fn cancel_deal(&self, deal_id: u64) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE deal SET cancelled_on = datetime('now', 'localtime'), updated_on = datetime('now', 'localtime')
+            WHERE deal_id = $1 AND cancelled_on IS NULL"#,
+        )
+        .bind(deal_id as i64)
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn restore_deal(&self, deal_id: u64) -> Result<()> {
+        sqlx::query("UPDATE deal SET cancelled_on = NULL WHERE deal_id = $1")
+            .bind(deal_id as i64)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Number of active deals per project
+    pub async fn count_deals(&self) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query_as("SELECT project, COUNT(*) FROM deal WHERE cancelled_on IS NULL GROUP BY project ORDER BY project")
+            .fetch_all(&self.db)
+            .await?;
+        Ok(rows)
+    }
+
     pub async fn read_deal_ids(&self) -> Result<Vec<u64>> {
         let records: Vec<HouseData> = sqlx::query_as("SELECT * FROM deal")
             .fetch_all(&self.db)
@@ -99,79 +231,186 @@ impl Db {
         Ok(res)
     }
 
+    pub async fn list_active_deal_ids(&self, project: &str) -> Result<Vec<u64>> {
+        let rows: Vec<(i64,)> =
+            sqlx::query_as("SELECT deal_id FROM deal WHERE project = $1 AND cancelled_on IS NULL ORDER BY deal_id")
+                .bind(project)
+                .fetch_all(&self.db)
+                .await?;
+        Ok(rows.into_iter().map(|r| r.0 as u64).collect())
+    }
+
     async fn get_deal(
         &self,
         project: &str,
         object_type: &str,
-        house: i32,
+        building: &Building,
         number: i32,
-    ) -> Result<HouseData> {
-        let rows = sqlx::query_as(
+    ) -> Result<Option<HouseData>> {
+        let row = sqlx::query_as(
             r#"
-            SELECT * FROM deal WHERE project = $1 AND object_type = $2 AND house = $3 AND object = $4 "#,
+            SELECT * FROM deal
+            WHERE project = $1 AND object_type = $2 AND house = $3 AND corpus IS $4 AND letter IS $5
+                AND object = $6 AND cancelled_on IS NULL"#,
         )
         .bind(project)
         .bind(object_type)
+        .bind(building.house)
+        .bind(building.corpus)
+        .bind(&building.letter)
+        .bind(number)
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(row)
+    }
+
+    /// Same house and number across all projects, object types, corpuses and letters
+    pub async fn find_deals(&self, house: i32, number: i32) -> Result<Vec<HouseData>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT * FROM deal WHERE house = $1 AND object = $2 AND cancelled_on IS NULL
+            ORDER BY project, object_type, corpus, letter"#,
+        )
         .bind(house)
         .bind(number)
-        .fetch_one(&self.db)
+        .fetch_all(&self.db)
         .await?;
         Ok(rows)
     }
+
+    pub async fn get_deal_by_id(&self, deal_id: u64) -> Result<HouseData> {
+        let row = sqlx::query_as("SELECT * FROM deal WHERE deal_id = $1")
+            .bind(deal_id as i64)
+            .fetch_one(&self.db)
+            .await?;
+        Ok(row)
+    }
+
+    pub async fn find_deal_by_id(&self, deal_id: u64) -> Result<Option<HouseData>> {
+        let row = sqlx::query_as("SELECT * FROM deal WHERE deal_id = $1")
+            .bind(deal_id as i64)
+            .fetch_optional(&self.db)
+            .await?;
+        Ok(row)
+    }
+}
+
+/// Object card text and its deal, None when there is no such object.
+/// `contacts` adds the buyer contacts, see `menu::contacts_visible`.
+pub async fn prepare_response(
+    db: &Db,
+    project: &str,
+    object_type: &str,
+    building: &Building,
+    number: i32,
+    contacts: bool,
+) -> Result<Option<(String, u64)>> {
+    match db.get_deal(project, object_type, building, number).await? {
+        Some(deal) => Ok(Some(card(db, &deal, contacts).await?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn prepare_response_by_id(db: &Db, deal_id: u64, contacts: bool) -> Result<Option<(String, u64)>> {
+    match db.find_deal_by_id(deal_id).await? {
+        Some(deal) => Ok(Some(card(db, &deal, contacts).await?)),
+        None => Ok(None),
+    }
+}
+
+async fn card(db: &Db, b: &HouseData, contacts: bool) -> Result<(String, u64)> {
+    let mut text = format_card(b);
+    if contacts {
+        text.push_str(&format_contacts(&db.list_contacts(b.deal_id).await?));
+    }
+    Ok((text, b.deal_id))
 }
 
-pub async fn get_house_numbers(project: &str, object_type: &str) -> Vec<i32> {
-    let db = Db::new().await;
-    let res = db.list_house_numbers(project, object_type).await;
-    res.unwrap_or_else(|e| {
-        error!("[get_house_numbers] {:?}", e);
-        vec![]
-    })
+/// Last day to hand the object over
+pub fn deadline(b: &HouseData) -> NaiveDate {
+    let period = find_project(&b.project).map_or(Period::default(), |p| p.period(&b.object_type));
+    deadline::deadline(b.created_on.date(), period, calendar())
 }
 
-pub async fn get_object_numbers(project: &str, object_type: &str, house: i32) -> Vec<i32> {
-    let db = Db::new().await;
-    let res = db.list_numbers(project, object_type, house).await;
-    res.unwrap_or_else(|e| {
-        error!("[get_object_numbers] {:?}", e);
-        vec![]
-    })
+fn format_card(b: &HouseData) -> String {
+    let template = find_project(&b.project)
+        .map_or_else(|| default_template(&b.object_type), |p| p.card(&b.object_type));
+    let details = card::render(&template, b);
+    let handover = match (&b.handover_date, &b.handed_by) {
+        (Some(date), Some(by)) => format!(" ({}, {})", date.format("%d.%m.%Y"), by),
+        (None, Some(by)) => format!(" ({})", by),
+        _ => "".to_string(),
+    };
+    let cancelled = match &b.cancelled_on {
+        Some(date) => format!("Сделка отменена: {}\n", date.format("%d.%m.%Y")),
+        None => "".to_string(),
+    };
+    format!(
+        "Проект: {}\nДом № {}\nТип объекта: {}\n№ {}\n{}Дата регистрации: {}\nПередать объект до: {}\nСтатус передачи: {}{}\nСделка: {}\n{}",
+        b.project,
+        b.building().label(),
+        b.object_type,
+        b.object,
+        details,
+        b.created_on.format("%d.%m.%Y"),
+        deadline(b).format("%d.%m.%Y"),
+        b.handover_status.label(),
+        handover,
+        b.deal_id,
+        cancelled,
+    )
 }
 
-pub async fn prepare_response(project: &str, object_type: &str, house: i32, number: i32) -> String {
-    let db = Db::new().await;
-    let result = db.get_deal(project, object_type, house, number).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{test_db, test_deal};
+
+    #[tokio::test]
+    async fn update_keeps_handover() {
+        let db = test_db().await;
+        let mut deal = DealForAdd { house: -1, ..test_deal(3001) };
+        db.create_deal(&deal).await.unwrap();
+        let now = NaiveDateTime::default();
+        db.set_handover_status(3001, HandoverStatus::Handed, now, "Иван").await.unwrap();
+
+        deal.house = 3;
+        assert!(db.update_deal(&deal).await.unwrap());
+        let stored = db.get_deal_by_id(3001).await.unwrap();
+        assert_eq!(stored.house, 3);
+        assert_eq!(stored.handover_status, HandoverStatus::Handed);
+        assert_eq!(db.count_deals().await.unwrap(), vec![("DNS Сити".to_string(), 1)]);
+
+        deal.deal_id = 42;
+        assert!(!db.update_deal(&deal).await.unwrap());
+    }
 
-    match result {
-        Ok(b) => {
-            let facing = if b.object_type.eq("Квартиры") {
-                format!("Тип отделки: {}\n", b.facing)
-            } else {
-                "".to_string()
+    #[tokio::test]
+    async fn corpus_and_letter_are_separate_buildings() {
+        let db = test_db().await;
+        let buildings = [
+            Building { house: 3, corpus: None, letter: None },
+            Building { house: 3, corpus: None, letter: Some("А".to_string()) },
+            Building { house: 3, corpus: Some(2), letter: None },
+        ];
+        for (deal_id, b) in (3001..).zip(&buildings) {
+            let deal = DealForAdd {
+                house: b.house,
+                corpus: b.corpus,
+                letter: b.letter.clone(),
+                ..test_deal(deal_id)
             };
-            let res = format!(
-                "Проект: {}\nДом № {}\nТип объекта: {}\n№ {}\n{}Дата регистрации: {}\nПередать объект до: {}\n",
-                b.project,
-                b.house,
-                b.object_type,
-                b.object,
-                facing,
-                b.created_on.format("%d.%m.%Y"),
-                b.created_on
-                    .add(Duration::from_secs(2592000)) // 30 days
-                    .format("%d.%m.%Y")
-            );
-
-            if res.is_empty() {
-                "Нет данных".to_string()
-            } else {
-                res
-            }
+            db.create_deal(&deal).await.unwrap();
         }
 
-        Err(e) => {
-            error!("Prepare response error: {}", e);
-            "Ошибка чтения данных".to_string()
+        assert_eq!(db.list_buildings("DNS Сити", "Квартиры").await.unwrap(), buildings);
+        for (deal_id, b) in (3001..).zip(&buildings) {
+            assert_eq!(db.list_numbers("DNS Сити", "Квартиры", b).await.unwrap(), vec![57]);
+            let deal = db.get_deal("DNS Сити", "Квартиры", b, 57).await.unwrap();
+            assert_eq!(deal.map(|d| d.deal_id), Some(deal_id));
         }
+        assert_eq!(buildings[1].label(), "3А");
+        assert_eq!(buildings[2].label(), "3, корпус 2");
+        assert_eq!(db.find_deals(3, 57).await.unwrap().len(), 3);
     }
 }
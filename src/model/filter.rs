@@ -0,0 +1,137 @@
+use crate::model::data::{FlexibleType, Lead};
+use serde::Deserialize;
+
+/// AmoCRM system status "Закрыто и не реализовано"
+pub const LOST_STATUS: u64 = 143;
+
+/// Which AmoCRM leads are treated as DKP deals.
+/// Fields and enums are matched by id, so renaming them in AmoCRM is harmless.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DealFilter {
+    pub field_id: u64,
+    #[serde(default)]
+    pub enum_ids: Vec<u64>,
+    /// Used only when `enum_ids` is empty (text fields)
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Empty means any pipeline
+    #[serde(default)]
+    pub pipeline_ids: Vec<u64>,
+    /// Empty means any status
+    #[serde(default)]
+    pub status_ids: Vec<u64>,
+}
+
+impl DealFilter {
+    pub fn matches(&self, lead: &Lead) -> bool {
+        if !self.pipeline_ids.is_empty() && !self.pipeline_ids.contains(&lead.pipeline_id) {
+            return false;
+        }
+        if !self.status_ids.is_empty() && !self.status_ids.contains(&lead.status_id) {
+            return false;
+        }
+        self.matches_contract(lead)
+    }
+
+    /// A stored deal is cancelled when it is lost or its contract type changed.
+    /// Moving on to another pipeline or status is not a cancellation.
+    pub fn is_cancelled(&self, lead: &Lead) -> bool {
+        lead.status_id == LOST_STATUS || !self.matches_contract(lead)
+    }
+
+    fn matches_contract(&self, lead: &Lead) -> bool {
+        lead.custom_fields_values
+            .iter()
+            .flatten()
+            .filter(|f| f.field_id == self.field_id)
+            .flat_map(|f| f.values.iter())
+            .any(|v| {
+                if self.enum_ids.is_empty() {
+                    match &v.value {
+                        FlexibleType::Str(s) => self.values.contains(s),
+                        FlexibleType::Int(i) => self.values.contains(&i.to_string()),
+                    }
+                } else {
+                    v.enum_id.is_some_and(|id| self.enum_ids.contains(&id))
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::data::Record;
+
+    fn leads() -> Vec<Lead> {
+        let record: Record = serde_json::from_str(include_str!("testdata/leads.json")).unwrap();
+        record._embedded.unwrap().leads
+    }
+
+    fn dkp() -> DealFilter {
+        DealFilter {
+            field_id: 1631153,
+            enum_ids: vec![4661181],
+            values: vec![],
+            pipeline_ids: vec![],
+            status_ids: vec![],
+        }
+    }
+
+    fn matched(filter: &DealFilter) -> Vec<u64> {
+        leads()
+            .iter()
+            .filter(|l| filter.matches(l))
+            .map(|l| l.id)
+            .collect()
+    }
+
+    #[test]
+    fn match_by_enum_id() {
+        // 3003 has the field renamed and an extra value, 3004 has no custom fields at all
+        assert_eq!(matched(&dkp()), vec![3001, 3003]);
+    }
+
+    #[test]
+    fn match_by_value() {
+        let filter = DealFilter {
+            enum_ids: vec![],
+            values: vec!["ДКП".to_string()],
+            ..dkp()
+        };
+        assert_eq!(matched(&filter), vec![3001]);
+    }
+
+    #[test]
+    fn match_by_pipeline_and_status() {
+        let filter = DealFilter {
+            pipeline_ids: vec![7000001],
+            ..dkp()
+        };
+        assert_eq!(matched(&filter), vec![3001]);
+
+        let filter = DealFilter {
+            status_ids: vec![142],
+            ..dkp()
+        };
+        assert_eq!(matched(&filter), vec![3003]);
+    }
+
+    #[test]
+    fn cancelled() {
+        let filter = DealFilter {
+            status_ids: vec![58000001],
+            ..dkp()
+        };
+        let cancelled: Vec<u64> = leads()
+            .iter()
+            .filter(|l| filter.is_cancelled(l))
+            .map(|l| l.id)
+            .collect();
+        assert_eq!(cancelled, vec![3002, 3004]);
+
+        let mut lead = leads().remove(0);
+        lead.status_id = LOST_STATUS;
+        assert!(filter.is_cancelled(&lead));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::model::Db;
+use crate::Result;
+use log::debug;
+use sqlx::types::chrono::NaiveDateTime;
+use std::str::FromStr;
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum HandoverStatus {
+    Pending,
+    Scheduled,
+    Handed,
+    Disputed,
+}
+
+impl HandoverStatus {
+    pub const ALL: [HandoverStatus; 4] = [
+        HandoverStatus::Pending,
+        HandoverStatus::Scheduled,
+        HandoverStatus::Handed,
+        HandoverStatus::Disputed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HandoverStatus::Pending => "pending",
+            HandoverStatus::Scheduled => "scheduled",
+            HandoverStatus::Handed => "handed",
+            HandoverStatus::Disputed => "disputed",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HandoverStatus::Pending => "Ожидает передачи",
+            HandoverStatus::Scheduled => "Передача назначена",
+            HandoverStatus::Handed => "Передан",
+            HandoverStatus::Disputed => "Спорный",
+        }
+    }
+}
+
+impl FromStr for HandoverStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        HandoverStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or(())
+    }
+}
+
+impl Db {
+    /// Returns false when there is no such deal or it was cancelled
+    pub async fn set_handover_status(
+        &self,
+        deal_id: u64,
+        status: HandoverStatus,
+        date: NaiveDateTime,
+        by: &str,
+    ) -> Result<bool> {
+        debug!("[set_handover_status] {} -> {:?} by {}", deal_id, status, by);
+        // Back to pending means nothing has been agreed yet.
+        // The date of a scheduled handover is not known to the bot, so it stays empty.
+        let (date, by) = match status {
+            HandoverStatus::Pending => (None, None),
+            HandoverStatus::Scheduled => (None, Some(by)),
+            _ => (Some(date), Some(by)),
+        };
+        let res = sqlx::query(
+            r#"
+            UPDATE deal SET handover_status = $1, handover_date = $2, handed_by = $3
+            WHERE deal_id = $4 AND cancelled_on IS NULL"#,
+        )
+        .bind(status)
+        .bind(date)
+        .bind(by)
+        .bind(deal_id as i64)
+        .execute(&self.db)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{test_db, test_deal};
+
+    #[tokio::test]
+    async fn set_status() {
+        let db = test_db().await;
+        db.create_deal(&test_deal(3001)).await.unwrap();
+
+        let now = NaiveDateTime::default();
+        assert!(db.set_handover_status(3001, HandoverStatus::Handed, now, "Иван").await.unwrap());
+        let deal = db.get_deal_by_id(3001).await.unwrap();
+        assert_eq!(deal.handover_status, HandoverStatus::Handed);
+        assert_eq!(deal.handed_by.as_deref(), Some("Иван"));
+
+        assert!(db.set_handover_status(3001, HandoverStatus::Pending, now, "Иван").await.unwrap());
+        let deal = db.get_deal_by_id(3001).await.unwrap();
+        assert_eq!(deal.handover_status, HandoverStatus::Pending);
+        assert!(deal.handover_date.is_none());
+
+        assert!(db.set_handover_status(3001, HandoverStatus::Scheduled, now, "Иван").await.unwrap());
+        let deal = db.get_deal_by_id(3001).await.unwrap();
+        assert!(deal.handover_date.is_none());
+        assert_eq!(deal.handed_by.as_deref(), Some("Иван"));
+
+        assert!(!db.set_handover_status(42, HandoverStatus::Handed, now, "Иван").await.unwrap());
+        db.cancel_deal(3001).await.unwrap();
+        assert!(!db.set_handover_status(3001, HandoverStatus::Handed, now, "Иван").await.unwrap());
+    }
+}
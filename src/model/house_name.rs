@@ -0,0 +1,200 @@
+//! Profitbase `houseName` like "Дом №3", "Дом 3, корпус 2", "Секция 1А" or "Паркинг 2"
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitKind {
+    Apartment,
+    Storage,
+    Parking,
+    Commercial,
+}
+
+impl UnitKind {
+    /// Object type as listed in `object_types` of the projects file
+    pub fn object_type(&self) -> &'static str {
+        match self {
+            UnitKind::Apartment => "Квартиры",
+            UnitKind::Storage => "Кладовки",
+            UnitKind::Parking => "Паркинг",
+            UnitKind::Commercial => "Коммерция",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HouseName {
+    pub kind: UnitKind,
+    /// Building number, the section number for names without one
+    pub house: i32,
+    pub letter: Option<String>,
+    pub corpus: Option<i32>,
+    /// Number with its letter, e.g. "1А"
+    pub section: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Slot {
+    House,
+    Corpus,
+    Section,
+    Letter,
+}
+
+/// None when no building or section number can be found
+pub fn parse(name: &str) -> Option<HouseName> {
+    let lower = name.to_lowercase();
+    let normalized: String = lower
+        .chars()
+        .map(|c| if matches!(c, '№' | ',' | '.' | '(' | ')' | '"' | '«' | '»') { ' ' } else { c })
+        .collect();
+
+    let mut kind = UnitKind::Apartment;
+    let mut house: Option<(i32, Option<String>)> = None;
+    let mut corpus = None;
+    let mut section: Option<(i32, Option<String>)> = None;
+    let mut letter = None;
+    // The word a following number belongs to, None after a word we don't know
+    let mut slot = Some(Slot::House);
+
+    for token in normalized.split_whitespace() {
+        // "д3" and "корп2" are split into the word and the number
+        let split = token.find(|c: char| c.is_ascii_digit()).unwrap_or(token.len());
+        let (word, number) = token.split_at(split);
+        let word = word.trim_end_matches('-');
+
+        if number.is_empty() && is_letter(word) && keyword(word).is_none() {
+            // "литер В", or a lone letter right after the house number: "Дом 3 А"
+            if slot == Some(Slot::Letter) {
+                letter = Some(word.to_uppercase());
+            } else if let Some((n, None)) = house {
+                house = Some((n, Some(word.to_uppercase())));
+            }
+            slot = None;
+            continue;
+        }
+        if !word.is_empty() {
+            slot = match unit_kind(word) {
+                Some(k) => {
+                    kind = k;
+                    Some(Slot::House)
+                }
+                None => keyword(word),
+            };
+        }
+        if number.is_empty() {
+            continue;
+        }
+
+        let Some(parsed) = parse_number(number) else {
+            slot = None;
+            continue;
+        };
+        match slot {
+            Some(Slot::House) if house.is_none() => house = Some(parsed),
+            Some(Slot::Corpus) => corpus = Some(parsed.0),
+            Some(Slot::Section) => section = Some(parsed),
+            Some(Slot::Letter) => letter = parsed.1,
+            _ => {}
+        }
+        slot = None;
+    }
+
+    let (house, house_letter) = house.or_else(|| section.clone())?;
+    Some(HouseName {
+        kind,
+        house,
+        letter: letter.or(house_letter),
+        corpus,
+        section: section.map(|(n, l)| format!("{}{}", n, l.unwrap_or_default())),
+    })
+}
+
+fn keyword(word: &str) -> Option<Slot> {
+    match word {
+        "дом" | "д" | "гп" | "здание" | "строение" | "стр" => Some(Slot::House),
+        "корпус" | "корп" | "к" => Some(Slot::Corpus),
+        "секция" | "сек" | "подъезд" | "под" => Some(Slot::Section),
+        "литер" | "литера" | "лит" => Some(Slot::Letter),
+        _ => None,
+    }
+}
+
+fn unit_kind(word: &str) -> Option<UnitKind> {
+    if word.starts_with("кладов") {
+        Some(UnitKind::Storage)
+    } else if word.starts_with("паркинг") || word.starts_with("парковк") || word.starts_with("машиномест") {
+        Some(UnitKind::Parking)
+    } else if word.starts_with("коммерч") || word.starts_with("нежил") || word.starts_with("офис") {
+        Some(UnitKind::Commercial)
+    } else {
+        None
+    }
+}
+
+fn is_letter(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!((chars.next(), chars.next()), (Some(c), None) if c.is_alphabetic())
+}
+
+/// "3", "3а" or "3-А", the letter is uppercased
+fn parse_number(token: &str) -> Option<(i32, Option<String>)> {
+    let digits_end = token.find(|c: char| !c.is_ascii_digit()).unwrap_or(token.len());
+    let number = token[..digits_end].parse().ok()?;
+    let rest = token[digits_end..].trim_start_matches('-');
+    match rest {
+        "" => Some((number, None)),
+        rest if is_letter(rest) => Some((number, Some(rest.to_uppercase()))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use UnitKind::*;
+
+    /// Name, kind, house, letter, corpus, section
+    type Case = (&'static str, UnitKind, i32, Option<&'static str>, Option<i32>, Option<&'static str>);
+
+    #[test]
+    fn house_names() {
+        let cases: &[Case] = &[
+            ("Дом №3", Apartment, 3, None, None, None),
+            ("Дом № 12", Apartment, 12, None, None, None),
+            ("3", Apartment, 3, None, None, None),
+            ("Дом 3, корпус 2", Apartment, 3, None, Some(2), None),
+            ("д. 3 корп. 2", Apartment, 3, None, Some(2), None),
+            ("Дом 3А", Apartment, 3, Some("А"), None, None),
+            ("Дом 3-б", Apartment, 3, Some("Б"), None, None),
+            ("Дом 3 А", Apartment, 3, Some("А"), None, None),
+            ("Дом 5, литер В", Apartment, 5, Some("В"), None, None),
+            ("Секция 1А", Apartment, 1, Some("А"), None, Some("1А")),
+            ("Дом 2, секция 4", Apartment, 2, None, None, Some("4")),
+            ("ГП-7", Apartment, 7, None, None, None),
+            ("Очередь 2, дом 5", Apartment, 5, None, None, None),
+            ("Кладовки Дом №3", Storage, 3, None, None, None),
+            ("Кладовые, дом 4", Storage, 4, None, None, None),
+            ("Паркинг 2", Parking, 2, None, None, None),
+            ("Подземная парковка, дом 1", Parking, 1, None, None, None),
+            ("Машиноместа корпус 3, дом 6", Parking, 6, None, Some(3), None),
+            ("Коммерческие помещения дом 3", Commercial, 3, None, None, None),
+            ("Нежилые помещения, секция 2", Commercial, 2, None, None, Some("2")),
+        ];
+        for &(name, kind, house, letter, corpus, section) in cases {
+            let expected = HouseName {
+                kind,
+                house,
+                letter: letter.map(str::to_string),
+                corpus,
+                section: section.map(str::to_string),
+            };
+            assert_eq!(parse(name), Some(expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn unparseable() {
+        for name in ["", "Паркинг", "Дом", "Корпус 2", "Очередь 2", "Дом 3/1", "ЖК Формат"] {
+            assert_eq!(parse(name), None, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::Result;
+use log::warn;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+/// Shared client for every CRM call
+pub fn http() -> &'static Http {
+    static INSTANCE: OnceLock<Http> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| Http::new(Policy::default()))
+}
+
+pub struct Policy {
+    /// Attempts after the first one
+    pub retries: u32,
+    /// Doubled on every retry
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Requests per second to a single host, AmoCRM allows 7
+    pub rps: u32,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            retries: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+            rps: 7,
+        }
+    }
+}
+
+pub struct Http {
+    client: Client,
+    policy: Policy,
+    /// Earliest time the next request to the host may start
+    slots: Mutex<HashMap<String, Instant>>,
+}
+
+impl Http {
+    pub fn new(policy: Policy) -> Self {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(30))
+            .build()
+            .expect("Unable to build HTTP client");
+        Http {
+            client,
+            policy,
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    /// Sends the request, retrying 429, 5xx and failed connections.
+    /// The last response is returned as is once retries run out.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let request = request.build()?;
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        let mut attempt = 0;
+        loop {
+            self.wait_turn(&host).await;
+            let next = request.try_clone().expect("Request body must not be a stream");
+            let delay = match self.client.execute(next).await {
+                Ok(response) if attempt < self.policy.retries && is_retryable(response.status()) => {
+                    warn!("[http] {} {}, attempt {}", request.url(), response.status(), attempt + 1);
+                    retry_after(&response).unwrap_or_else(|| self.backoff(attempt))
+                }
+                Err(e) if attempt < self.policy.retries && is_transient(&e) => {
+                    warn!("[http] {} {:?}, attempt {}", request.url(), e, attempt + 1);
+                    self.backoff(attempt)
+                }
+                result => return Ok(result?),
+            };
+            sleep(delay.min(self.policy.max_delay)).await;
+            attempt += 1;
+        }
+    }
+
+    /// For requests that must not reach the server twice, e.g. spending a single-use token
+    pub async fn send_once(&self, request: RequestBuilder) -> Result<Response> {
+        let request = request.build()?;
+        self.wait_turn(request.url().host_str().unwrap_or_default()).await;
+        Ok(self.client.execute(request).await?)
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.policy.base_delay * 2u32.saturating_pow(attempt)
+    }
+
+    async fn wait_turn(&self, host: &str) {
+        let interval = Duration::from_secs(1) / self.policy.rps.max(1);
+        let now = Instant::now();
+        let start = {
+            let mut slots = self.slots.lock().unwrap();
+            let slot = slots.entry(host.to_string()).or_insert(now);
+            let start = (*slot).max(now);
+            *slot = start + interval;
+            start
+        };
+        sleep(start - now).await;
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Only a request that never got to the server is safe to repeat,
+/// after a timeout or a reset it may already have been processed
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_connect()
+}
+
+/// Only the delay-seconds form, AmoCRM and Profitbase don't send dates
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?.parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn fast(rps: u32) -> Http {
+        Http::new(Policy {
+            retries: 2,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(2),
+            rps,
+        })
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .with_priority(3)
+            .mount(&server)
+            .await;
+
+        let http = fast(100);
+        let started = Instant::now();
+        let response = http.send(http.get(&server.uri())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let http = fast(100);
+        let response = http.send(http.get(&server.uri())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Connection refused is retried as well, then the error is returned
+        let http = fast(100);
+        let response = http.send(http.get("http://127.0.0.1:1")).await;
+        assert!(response.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_once_does_not_retry() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let http = fast(100);
+        let response = http.send_once(http.post(&server.uri())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn limits_rate_per_host() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let http = fast(10);
+        let started = Instant::now();
+        for _ in 0..3 {
+            http.send(http.get(&server.uri())).await.unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
@@ -4,57 +4,86 @@ use sqlx::migrate::MigrateDatabase;
 use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::{Sqlite, SqlitePool};
 
+pub mod amo;
+pub mod amo_auth;
+pub mod contact;
 pub mod deal;
+pub mod filter;
+pub mod handover;
+pub mod house_name;
+pub mod http;
+pub mod profit;
+pub mod quarantine;
+pub mod reminder;
 pub mod sync;
+pub mod sync_run;
+pub mod sync_state;
+pub mod user;
 
 mod data;
 
+/// Cheap to clone, all clones share one connection pool
+#[derive(Clone)]
 pub struct Db {
     pub db: SqlitePool,
 }
 
-impl Db {
-    pub async fn new() -> Db {
-        init_db().await.unwrap()
-    }
-}
-
-async fn create_schema(db_url: &str) -> Result<()> {
-    let pool = SqlitePool::connect(db_url).await?;
-    let qry = r#"
-    CREATE TABLE IF NOT EXISTS deal
-    (
-        id              INTEGER PRIMARY KEY AUTOINCREMENT,
-        deal_id         BIGINTEGER          NOT NULL,
-        project         TEXT                NOT NULL,
-        house           INTEGER             NOT NULL,
-        object_type     TEXT                NOT NULL,
-        object          INTEGER             NOT NULL,
-        facing          TEXT,
-        created_on      DATETIME DEFAULT    (datetime('now', 'localtime')),
-        updated_on      DATETIME DEFAULT    (datetime('now', 'localtime'))
-    );
-    "#;
-    let _ = sqlx::query(qry).execute(&pool).await?;
-    pool.close().await;
-    Ok(())
-}
-
 pub async fn init_db() -> Result<Db> {
     if !Sqlite::database_exists(&config().DB_URL)
         .await
         .unwrap_or(false)
     {
         Sqlite::create_database(&config().DB_URL).await?;
-        match create_schema(&config().DB_URL).await {
-            Ok(_) => log::info!("database created successfully"),
-            Err(e) => panic!("{}", e),
-        }
+        log::info!("database created successfully");
     }
     let db = SqlitePoolOptions::new()
         .max_connections(5)
         .connect(&config().DB_URL)
         .await?;
 
+    // Applied versions are tracked in `_sqlx_migrations`
+    sqlx::migrate!().run(&db).await?;
+
     Ok(Db { db })
 }
+
+/// In-memory database with all migrations applied
+#[cfg(test)]
+pub async fn test_db() -> Db {
+    // Every connection to :memory: is a separate database, so keep just one
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&db).await.unwrap();
+    Db { db }
+}
+
+/// Flat № 57 in house 3 of "DNS Сити", override the fields a test cares about
+#[cfg(test)]
+pub fn test_deal(deal_id: u64) -> deal::DealForAdd {
+    deal::DealForAdd {
+        deal_id,
+        project: "DNS Сити".to_string(),
+        house: 3,
+        object_type: "Квартиры".to_string(),
+        object: 57,
+        facing: "".to_string(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn deal_id_is_unique() {
+        let db = test_db().await;
+        let deal = test_deal(3_000_000_001);
+        db.create_deal(&deal).await.unwrap();
+        assert!(db.create_deal(&deal).await.is_err());
+        assert_eq!(db.read_deal_ids().await.unwrap(), vec![3_000_000_001]);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::error::Error;
+use crate::model::data::ProfitRecord;
+use crate::model::deal::DealForAdd;
+use crate::model::house_name;
+use crate::model::http::http;
+use crate::project::Project;
+use crate::Result;
+use log::debug;
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::types::chrono::DateTime;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Used when the auth response has no `remaining_time`
+const DEFAULT_TOKEN_TTL: u64 = 3600;
+/// Cut from the Profitbase TTL, a cached token is dropped before the server stops accepting it
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+struct CachedToken {
+    token: String,
+    expires: Instant,
+}
+
+/// Access tokens by Profitbase url, shared by all runs until the bot restarts
+fn tokens() -> &'static Mutex<HashMap<String, CachedToken>> {
+    static INSTANCE: OnceLock<Mutex<HashMap<String, CachedToken>>> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Cached token of the project's Profitbase, authenticates when missing or expired
+pub async fn profit_token(project: &Project) -> Result<String> {
+    if let Some(cached) = tokens().lock().unwrap().get(&project.prof_url)
+        && cached.expires > Instant::now()
+    {
+        return Ok(cached.token.clone());
+    }
+
+    let (token, ttl) = get_profit_token(&project.prof_url, &project.prof_api_key).await?;
+    tokens().lock().unwrap().insert(
+        project.prof_url.clone(),
+        CachedToken {
+            token: token.clone(),
+            expires: Instant::now() + ttl.saturating_sub(EXPIRY_MARGIN),
+        },
+    );
+    Ok(token)
+}
+
+fn invalidate_token(url: &str) {
+    tokens().lock().unwrap().remove(url);
+}
+
+/// A 401 drops the cached token and the request is repeated once with a fresh one
+pub async fn get_profit_data(deal_id: u64, project: &Project) -> Result<DealForAdd> {
+    let token = profit_token(project).await?;
+    let response = match fetch_deal(deal_id, &project.prof_url, &token).await? {
+        Some(response) => response,
+        None => {
+            debug!("[get_profit_data] token rejected, authenticating again");
+            invalidate_token(&project.prof_url);
+            let token = profit_token(project).await?;
+            fetch_deal(deal_id, &project.prof_url, &token)
+                .await?
+                .ok_or(Error::ProfitAuthFailed)?
+        }
+    };
+
+    if response.status() == StatusCode::OK {
+        debug!("JSON parse");
+        let data = response.json::<ProfitRecord>().await?;
+
+        debug!("received: {:?}", data);
+        if data.status == "success" {
+            let p = data.data.first().ok_or(Error::ProfitEmptyData)?;
+            // The deal goes to quarantine and the admin sees the name
+            let name = house_name::parse(&p.house_name)
+                .ok_or_else(|| Error::UnknownHouseName(p.house_name.clone()))?;
+
+            // soldAt
+            let created_on = DateTime::parse_from_str(
+                format!("{} +0000", p.sold_at).as_str(),
+                "%Y-%m-%d %H:%M %z",
+            )
+            .unwrap_or(Default::default())
+            .naive_local();
+            let attrs = p.attributes.clone();
+
+            Ok(DealForAdd {
+                deal_id,
+                project: project.name.clone(),
+                house: name.house,
+                object_type: name.kind.object_type().to_string(),
+                object: p.number.parse::<i32>()?,
+                facing: attrs.facing.unwrap_or("".to_string()),
+                created_on,
+                property_id: p.id,
+                rooms: p.rooms_amount,
+                floor: p.floor,
+                section: p.section_name.clone().or(name.section),
+                area: p.area.as_ref().and_then(|a| a.area_total),
+                price: p.price.as_ref().and_then(|p| p.value),
+                corpus: name.corpus,
+                letter: name.letter,
+            })
+        } else {
+            Err(Error::ProfitGetDataFailed)
+        }
+    } else {
+        Err(Error::ProfitGetDataFailed)
+    }
+}
+
+/// None when the token is rejected
+async fn fetch_deal(deal_id: u64, url: &str, token: &str) -> Result<Option<Response>> {
+    let url = format!("{}/property/deal/{}?access_token={}", url, deal_id, token);
+
+    debug!("fetching {}", url);
+    let request = http().get(&url).header("Content-Type", "application/json");
+    let response = http().send(request).await?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return Ok(None);
+    }
+    Ok(Some(response))
+}
+
+#[derive(Deserialize)]
+struct AuthResponse {
+    pub access_token: String,
+    /// Seconds until the token expires
+    pub remaining_time: Option<u64>,
+}
+async fn get_profit_token(url: &str, api_key: &str) -> Result<(String, Duration)> {
+    let payload = json!({
+      "type": "api-app",
+      "credentials": {
+        "pb_api_key": api_key,
+      }
+    });
+    let request = http().post(&format!("{url}/authentication")).json(&payload);
+
+    let result = http().send(request).await?;
+
+    if result.status() == StatusCode::OK {
+        let auth = result.json::<AuthResponse>().await?;
+        debug!("Profitbase Token: {:?}, expires in {:?}", auth.access_token, auth.remaining_time);
+        let ttl = Duration::from_secs(auth.remaining_time.unwrap_or(DEFAULT_TOKEN_TTL));
+        return Ok((auth.access_token, ttl));
+    }
+
+    Err(Error::ProfitAuthFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn parse_date() {
+        let str_date = "2025-03-12 04:38 +0000";
+        let res = DateTime::parse_from_str(str_date, "%Y-%m-%d %H:%M %z");
+        println!("{:?}", res);
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn refreshes_rejected_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/authentication"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "access_token": "old" })))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/authentication"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "access_token": "new", "remaining_time": 86400 })),
+            )
+            .expect(1)
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("access_token", "old"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("access_token", "new"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "success",
+                "data": [{
+                    "number": "57", "houseName": "Дом №3", "projectName": "DNS Сити",
+                    "attributes": { "facing": "Чистовая" }, "soldAt": "2025-03-12 04:38"
+                }]
+            })))
+            .mount(&server)
+            .await;
+
+        let project: Project = toml::from_str(&format!(
+            r#"
+            name = "DNS Сити"
+            amo_url = ""
+            amo_token = ""
+            prof_url = "{}"
+            prof_api_key = "key"
+            object_types = []
+            filter = {{ field_id = 0 }}
+            "#,
+            server.uri()
+        ))
+        .unwrap();
+
+        // The second deal reuses the cached token without authenticating
+        for deal_id in [3001, 3002] {
+            let deal = get_profit_data(deal_id, &project).await.unwrap();
+            assert_eq!((deal.deal_id, deal.house, deal.object), (deal_id, 3, 57));
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::model::Db;
+use crate::Result;
+use log::debug;
+
+/// Deals failing this many times are no longer retried on every sync,
+/// only when the lead is updated in AmoCRM again
+pub const MAX_ATTEMPTS: i64 = 20;
+
+impl Db {
+    /// Returns how many times the deal has failed so far
+    pub async fn quarantine_deal(&self, deal_id: u64, project: &str, reason: &str) -> Result<i64> {
+        debug!("[quarantine_deal] {} {}: {}", project, deal_id, reason);
+        let (attempts,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO quarantine (deal_id, project, reason) VALUES ($1, $2, $3)
+            ON CONFLICT(deal_id) DO UPDATE SET
+                reason = excluded.reason,
+                attempts = attempts + 1,
+                last_failed = datetime('now', 'localtime')
+            RETURNING attempts"#,
+        )
+        .bind(deal_id as i64)
+        .bind(project)
+        .bind(reason)
+        .fetch_one(&self.db)
+        .await?;
+        Ok(attempts)
+    }
+
+    pub async fn release_deal(&self, deal_id: u64) -> Result<()> {
+        sqlx::query("DELETE FROM quarantine WHERE deal_id = $1")
+            .bind(deal_id as i64)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Deals still due for a retry
+    pub async fn list_quarantined(&self, project: &str) -> Result<Vec<u64>> {
+        let rows: Vec<(i64,)> =
+            sqlx::query_as("SELECT deal_id FROM quarantine WHERE project = $1 AND attempts < $2")
+                .bind(project)
+                .bind(MAX_ATTEMPTS)
+                .fetch_all(&self.db)
+                .await?;
+        Ok(rows.into_iter().map(|r| r.0 as u64).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_db;
+
+    #[tokio::test]
+    async fn counts_attempts() {
+        let db = test_db().await;
+        assert_eq!(db.quarantine_deal(3001, "DNS Сити", "404").await.unwrap(), 1);
+        assert_eq!(db.quarantine_deal(3001, "DNS Сити", "404").await.unwrap(), 2);
+        assert_eq!(db.list_quarantined("DNS Сити").await.unwrap(), vec![3001]);
+        assert!(db.list_quarantined("ЖК Формат").await.unwrap().is_empty());
+
+        db.release_deal(3001).await.unwrap();
+        assert!(db.list_quarantined("DNS Сити").await.unwrap().is_empty());
+
+        for _ in 0..MAX_ATTEMPTS {
+            db.quarantine_deal(3002, "DNS Сити", "UnknownHouseName").await.unwrap();
+        }
+        assert!(db.list_quarantined("DNS Сити").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+use crate::model::deal::HouseData;
+use crate::model::Db;
+use crate::Result;
+use chrono::Days;
+use chrono::Datelike;
+use sqlx::types::chrono::NaiveDate;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// Objects not handed over yet whose deadline is close or already passed
+pub struct Reminders {
+    pub overdue: Vec<(HouseData, NaiveDate)>,
+    pub upcoming: Vec<(HouseData, NaiveDate)>,
+    /// Deadline years of all objects still to hand over
+    pub years: BTreeSet<i32>,
+}
+
+impl Db {
+    pub async fn list_not_handed(&self) -> Result<Vec<HouseData>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT * FROM deal WHERE handover_status != 'handed' AND cancelled_on IS NULL
+            ORDER BY project, object_type, house, corpus, letter, object"#,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(rows)
+    }
+}
+
+pub async fn collect_reminders(
+    db: &Db,
+    today: NaiveDate,
+    days: u64,
+    deadline: impl Fn(&HouseData) -> NaiveDate,
+) -> Result<Reminders> {
+    let horizon = today + Days::new(days);
+    let mut reminders = Reminders {
+        overdue: vec![],
+        upcoming: vec![],
+        years: BTreeSet::new(),
+    };
+    for deal in db.list_not_handed().await? {
+        let until = deadline(&deal);
+        reminders.years.insert(until.year());
+        if until < today {
+            reminders.overdue.push((deal, until));
+        } else if until <= horizon {
+            reminders.upcoming.push((deal, until));
+        }
+    }
+    Ok(reminders)
+}
+
+impl Reminders {
+    pub fn is_empty(&self) -> bool {
+        self.overdue.is_empty() && self.upcoming.is_empty()
+    }
+
+    pub fn group_message(&self, days: u64) -> String {
+        let mut res = "Сроки передачи объектов\n".to_string();
+        if !self.overdue.is_empty() {
+            res.push_str("\nПросрочено:\n");
+            write_grouped(&mut res, &self.overdue);
+        }
+        if !self.upcoming.is_empty() {
+            let _ = writeln!(res, "\nИстекает в ближайшие {} дн.:", days);
+            write_grouped(&mut res, &self.upcoming);
+        }
+        res
+    }
+
+    pub fn admin_message(&self) -> Option<String> {
+        if self.overdue.is_empty() {
+            return None;
+        }
+        let mut res = format!("Просрочена передача {} объектов:\n", self.overdue.len());
+        write_grouped(&mut res, &self.overdue);
+        Some(res)
+    }
+}
+
+fn write_grouped(output: &mut String, deals: &[(HouseData, NaiveDate)]) {
+    let mut project = "";
+    for (b, until) in deals {
+        if b.project != project {
+            project = &b.project;
+            let _ = writeln!(output, "Проект: {}", project);
+        }
+        let _ = writeln!(
+            output,
+            "Дом № {} {} № {} до {} ({})",
+            b.building().label(),
+            b.object_type,
+            b.object,
+            until.format("%d.%m.%Y"),
+            b.handover_status.label()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::deal::DealForAdd;
+    use crate::model::handover::HandoverStatus;
+    use crate::model::{test_db, test_deal};
+
+    async fn add(db: &Db, deal_id: u64, object: i32, created_on: &str) {
+        let created_on = NaiveDate::parse_from_str(created_on, "%d.%m.%Y")
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let deal = DealForAdd {
+            object,
+            created_on,
+            ..test_deal(deal_id)
+        };
+        db.create_deal(&deal).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn split_overdue_and_upcoming() {
+        let db = test_db().await;
+        add(&db, 1, 10, "01.02.2025").await; // overdue
+        add(&db, 2, 11, "20.02.2025").await; // due within 5 days
+        add(&db, 3, 12, "10.03.2025").await; // far away
+        add(&db, 4, 13, "01.02.2025").await; // overdue but handed
+        let date = NaiveDate::default().and_hms_opt(0, 0, 0).unwrap();
+        db.set_handover_status(4, HandoverStatus::Handed, date, "Иван")
+            .await
+            .unwrap();
+        let lettered = DealForAdd {
+            object: 14,
+            corpus: Some(2),
+            letter: Some("А".to_string()),
+            created_on: NaiveDate::from_ymd_opt(2025, 2, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            ..test_deal(5)
+        };
+        db.create_deal(&lettered).await.unwrap(); // overdue, in another building
+
+        let today = NaiveDate::from_ymd_opt(2025, 3, 18).unwrap();
+        let deadline = |b: &HouseData| b.created_on.date() + Days::new(30);
+        let reminders = collect_reminders(&db, today, 5, deadline).await.unwrap();
+        let objects = |list: &[(HouseData, NaiveDate)]| list.iter().map(|d| d.0.object).collect::<Vec<_>>();
+        assert_eq!(objects(&reminders.overdue), vec![10, 14]);
+        assert_eq!(objects(&reminders.upcoming), vec![11]);
+        assert_eq!(reminders.years, BTreeSet::from([2025]));
+        let message = reminders.admin_message().unwrap();
+        assert!(message.contains("Дом № 3 Квартиры № 10 до 03.03.2025"));
+        assert!(message.contains("Дом № 3А, корпус 2 Квартиры № 14 до 03.03.2025"));
+    }
+}
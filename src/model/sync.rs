@@ -1,207 +1,805 @@
-use crate::config::config;
 use crate::error::Error;
-use crate::model::data::FlexibleType::Str;
-use crate::model::data::{CustomField, ProfitRecord, Record, Val};
-use crate::model::deal::DealForAdd;
+use crate::model::amo::{get_lead, lead_stream, MAX_PAGES};
+use crate::model::amo_auth::{amo_access_token, is_unauthorized, reauth_instructions, refresh_amo_token};
+use crate::model::contact::fetch_contacts;
+use crate::model::data::{ContactRef, Lead};
+use crate::model::deal::{DealForAdd, HouseData};
+use crate::model::profit::{get_profit_data, profit_token};
+use crate::model::quarantine::MAX_ATTEMPTS;
+use crate::model::sync_run::Trigger;
 use crate::model::Db;
+use crate::project::{find_project, projects, Project};
 use crate::Result;
-use log::{debug, info};
-use reqwest::Client;
-use serde::Deserialize;
-use serde_json::json;
-use sqlx::types::chrono::DateTime;
-use std::fmt::Write;
-
-pub async fn sync() -> Result<(bool, String)> {
-    let db = Db::new().await;
-
-    let client = Client::new()
-        .get(format!(
-            "{}&filter[created_at][from]=1600437670",
-            config().AMO_CITY_URL
-        ))
-        .header("Authorization", format!("Bearer {}", config().AMO_CITY_TOKEN));
+use futures::TryStreamExt;
+use log::{debug, error, info};
+use sqlx::types::chrono::Local;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::pin::pin;
+use tokio::sync::Mutex;
+
+/// Serializes runs so cron, /sync and webhooks never store the same deal twice
+static RUN_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Lead ids per request of the refresh pass, same as the AmoCRM page size
+const REFRESH_BATCH: usize = 250;
 
-    let result = client.send().await?;
+/// Stored for unparseable house names before they went to quarantine
+const UNKNOWN_HOUSE: i32 = -1;
+
+pub struct ProjectReport {
+    pub project: String,
+    /// Leads received from AmoCRM before the filter, up to the error if any
+    pub leads_seen: usize,
+    /// Deals put into quarantine by this run
+    pub skipped: Vec<SkippedDeal>,
+    /// Stored deals Profitbase now reports differently
+    pub changed: Vec<DealChange>,
+    /// Lost in AmoCRM, deleted or no longer DKP
+    pub cancelled: Vec<HouseData>,
+    /// Deals stored with an object type missing from the project `object_types`
+    pub unlisted: Vec<(u64, String)>,
+    pub result: Result<Vec<DealForAdd>>,
+}
 
-    if result.status() == reqwest::StatusCode::NO_CONTENT {
-        return Ok((false, "Новых сделок не найдено".to_string()));
+impl ProjectReport {
+    pub fn new(project: &str) -> Self {
+        ProjectReport {
+            project: project.to_string(),
+            leads_seen: 0,
+            skipped: vec![],
+            changed: vec![],
+            cancelled: vec![],
+            unlisted: vec![],
+            result: Ok(vec![]),
+        }
     }
+}
 
-    let mut data = result.json::<Record>().await?;
+pub struct SkippedDeal {
+    pub deal_id: u64,
+    pub reason: String,
+    pub attempts: i64,
+}
 
-    let mut next = data._links.next.take();
-    debug!("next: {:?}", next);
+pub struct DealChange {
+    pub deal: DealForAdd,
+    /// Human readable, e.g. "дом № 3 → 4"
+    pub changes: Vec<String>,
+}
 
-    let mut leads = extract_deal_ids(data);
+pub struct SyncReport {
+    pub projects: Vec<ProjectReport>,
+}
 
-    while next.is_some() {
-        let client = Client::new()
-            .get(format!(
-                "{}&filter[created_at][from]={}",
-                config().AMO_CITY_URL,
-                next.as_ref().unwrap().href
-            ))
-            .header("Authorization", format!("Bearer {}", config().AMO_CITY_TOKEN));
-        let mut data = client.send().await?.json::<Record>().await?;
+impl SyncReport {
+    /// True when at least one project got new deals
+    pub fn have_data(&self) -> bool {
+        self.projects
+            .iter()
+            .any(|p| matches!(&p.result, Ok(deals) if !deals.is_empty()))
+    }
 
-        next = data._links.next.take();
-        debug!("next in while: {:?}", next);
-        let leads_in_while = extract_deal_ids(data);
+    pub fn errors(&self) -> Vec<String> {
+        self.projects
+            .iter()
+            .filter_map(|p| match &p.result {
+                Err(e @ Error::AmoAuthFailed(_)) => Some(format!(
+                    "Проект: {}\n{}\n{}",
+                    p.project,
+                    e,
+                    reauth_instructions(&p.project)
+                )),
+                Err(e) => Some(format!("Проект: {}\n{}", p.project, e)),
+                Ok(_) => None,
+            })
+            .collect()
+    }
 
-        leads.extend(leads_in_while);
+    /// Admin notice about deals that failed for the first time or ran out of retries,
+    /// the ones still failing in between are only counted
+    pub fn skipped_summary(&self) -> Option<String> {
+        let mut text = String::new();
+        let mut given_up = String::new();
+        let mut retried = 0;
+        for p in &self.projects {
+            for s in &p.skipped {
+                let line = format!("{}, сделка {}: {}\n", p.project, s.deal_id, s.reason);
+                match s.attempts {
+                    1 => text.push_str(&line),
+                    MAX_ATTEMPTS => given_up.push_str(&line),
+                    _ => retried += 1,
+                }
+            }
+        }
+        if text.is_empty() && given_up.is_empty() {
+            return None;
+        }
+        let mut summary = String::new();
+        if !text.is_empty() {
+            summary.push_str(&format!("Сделки отложены в карантин, повтор при следующей синхронизации:\n{}", text));
+        }
+        if !given_up.is_empty() {
+            summary.push_str(&format!(
+                "Сделки не загружены после {} попыток, повтор только после изменения сделки в AmoCRM:\n{}",
+                MAX_ATTEMPTS, given_up
+            ));
+        }
+        if retried > 0 {
+            summary.push_str(&format!("Ещё не загружено после повторов: {}\n", retried));
+        }
+        Some(summary)
     }
 
-    let response = if !leads.is_empty() {
-        let mut new_data: Vec<DealForAdd> = vec![];
-        let saved_ids = db.read_deal_ids().await?;
-        let token = get_profit_token(&config().PROF_CITY_URL, &config().PROF_CITY_API_KEY).await?;
-        for lead in leads {
-            if saved_ids.contains(&lead) {
-                continue;
+    /// The menu lists only the configured object types, so these deals can't be opened from it
+    pub fn unlisted_summary(&self) -> Option<String> {
+        let mut text = String::new();
+        for p in &self.projects {
+            let mut types: Vec<&str> = p.unlisted.iter().map(|(_, t)| t.as_str()).collect();
+            types.sort();
+            types.dedup();
+            for t in types {
+                let ids: Vec<String> =
+                    p.unlisted.iter().filter(|(_, u)| u == t).map(|(id, _)| id.to_string()).collect();
+                text.push_str(&format!("{}, «{}»: {}\n", p.project, t, ids.join(" ")));
             }
-            let full_data = get_profit_data(lead, &config().PROF_CITY_URL, &token).await?;
-            db.create_deal(&full_data).await?;
-            new_data.push(full_data);
-        }
-
-        if new_data.is_empty() {
-            (false, "Новых сделок не найдено".to_string())
-        } else {
-            let res =
-                new_data
-                    .iter()
-                    .fold("Проект: Сити\n".to_string(), |mut output, b| {
-                        let _ = writeln!(
-                            output,
-                            "Дом № {} {} № {}, ",
-                            b.house, b.object_type, b.object
-                        );
-                        output
-                    });
-            (true, res)
         }
-    } else {
-        (false, "Синхронизация выполнена".to_string())
-    };
+        if text.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "Типов объектов нет в object_types проекта, сделки не видны в меню:\n{}\
+             Добавьте типы в файл проектов",
+            text
+        ))
+    }
 
-    db.db.close().await;
-    Ok(response)
-}
-
-fn extract_deal_ids(record: Record) -> Vec<u64> {
-    let leads = record
-        ._embedded
-        .leads
-        .iter()
-        .filter(|l| {
-            l.custom_fields_values.contains(&CustomField {
-                field_id: 1631153,
-                field_name: "Тип договора".to_string(),
-                values: vec![Val {
-                    value: Str("ДКП".to_string()),
-                    enum_id: Some(4661181),
-                }],
-            })
-        })
-        .map(|l| l.id)
-        .collect::<Vec<_>>();
-
-    info!("extractor leads {:?}", leads);
-    leads
-}
-
-async fn get_profit_data(deal_id: u64, url: &str, token: &str) -> Result<DealForAdd> {
-    let url = format!("{}/property/deal/{}?access_token={}", url, deal_id, token);
-
-    debug!("fetching {}", url);
-    let response = Client::new()
-        .get(url)
-        .header("Content-Type", "application/json")
-        .send()
-        .await?;
-
-    if response.status() == reqwest::StatusCode::OK {
-        debug!("JSON parse");
-        let data = response.json::<ProfitRecord>().await?;
-
-        debug!("received: {:?}", data);
-        if data.status == "success" {
-            let p = data.data.first().unwrap();
-            let object_type = if p.house_name.contains("Кладовк") {
-                "Кладовки".to_string()
-            } else {
-                "Квартиры".to_string()
+    /// Everything the admin has to look at after a run
+    pub fn admin_notices(&self) -> Vec<String> {
+        let mut notices = self.errors();
+        notices.extend(self.skipped_summary());
+        notices.extend(self.unlisted_summary());
+        notices
+    }
+
+    /// Group notice about changed and cancelled deals
+    pub fn digest(&self) -> Option<String> {
+        let mut text = String::new();
+        for p in self.projects.iter().filter(|p| !p.changed.is_empty() || !p.cancelled.is_empty()) {
+            text.push_str(&format!("Проект: {}\n", p.project));
+            for c in &p.changed {
+                text.push_str(&format!("Изменена сделка {}: {}\n", c.deal.deal_id, c.changes.join(", ")));
+            }
+            for d in &p.cancelled {
+                text.push_str(&format!(
+                    "Отменена сделка {}: дом № {} {} № {}\n",
+                    d.deal_id,
+                    d.building().label(),
+                    d.object_type,
+                    d.object
+                ));
+            }
+        }
+        if text.is_empty() {
+            return None;
+        }
+        Some(format!("Изменения по сделкам:\n{}", text))
+    }
+}
+
+impl Display for SyncReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for p in &self.projects {
+            writeln!(f, "Проект: {}", p.project)?;
+            match &p.result {
+                Ok(deals) if deals.is_empty() => writeln!(f, "Новых сделок не найдено")?,
+                Ok(deals) => {
+                    for b in deals {
+                        writeln!(f, "Дом № {} {} № {}, ", b.building().label(), b.object_type, b.object)?;
+                    }
+                }
+                Err(_) => writeln!(f, "Ошибка синхронизации")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `full` ignores the saved watermark and rescans the whole lead history.
+/// Every run is recorded in `sync_run` along with what started it.
+pub async fn sync(db: &Db, full: bool, trigger: Trigger) -> Result<SyncReport> {
+    let _guard = RUN_LOCK.lock().await;
+    let started = Local::now().naive_local();
+    let mut reports = vec![];
+    for project in projects() {
+        let mut report = ProjectReport::new(&project.name);
+        let mut result = sync_project(db, project, full, &mut report).await;
+        if let Err(e) = &result
+            && is_unauthorized(project, e)
+        {
+            // Revoked before it expired, start over once with a fresh token
+            report = ProjectReport::new(&project.name);
+            result = match refresh_amo_token(db, project).await {
+                Ok(_) => sync_project(db, project, full, &mut report).await,
+                Err(e) => Err(e),
             };
+        }
+        if let Err(e) = &result {
+            error!("[sync] project {}: {:?}", project.name, e);
+        }
+        report.result = result;
+        reports.push(report);
+    }
 
-            let house_parts = p.house_name.split('№').collect::<Vec<_>>();
-            let house = if house_parts.len() < 2 {
-                house_parts[0].to_string()
-            } else {
-                house_parts[1].to_string()
+    let report = SyncReport { projects: reports };
+    // The deals are stored already, losing the history row is not worth failing the run
+    if let Err(e) = db.save_sync_run(started, Local::now().naive_local(), trigger, &report).await {
+        error!("[sync] unable to save run: {:?}", e);
+    }
+    Ok(report)
+}
+
+/// Leads from a webhook, same filter and quarantine as the scheduled sync.
+/// Only runs that touched a deal are recorded in the history.
+pub async fn sync_leads(db: &Db, project: &Project, ids: &[u64]) -> SyncReport {
+    let _guard = RUN_LOCK.lock().await;
+    let started = Local::now().naive_local();
+    let mut report = ProjectReport::new(&project.name);
+    let mut result = sync_lead_ids(db, project, ids, &mut report).await;
+    if let Err(e) = &result
+        && is_unauthorized(project, e)
+    {
+        report = ProjectReport::new(&project.name);
+        result = match refresh_amo_token(db, project).await {
+            Ok(_) => sync_lead_ids(db, project, ids, &mut report).await,
+            Err(e) => Err(e),
+        };
+    }
+    if let Err(e) = &result {
+        error!("[sync_leads] project {}: {:?}", project.name, e);
+    }
+    report.result = result;
+
+    let report = SyncReport {
+        projects: vec![report],
+    };
+    let touched = report.have_data()
+        || !report.errors().is_empty()
+        || report.skipped_summary().is_some()
+        || report.unlisted_summary().is_some()
+        || report.digest().is_some();
+    if touched
+        && let Err(e) = db
+            .save_sync_run(started, Local::now().naive_local(), Trigger::Webhook, &report)
+            .await
+    {
+        error!("[sync_leads] unable to save run: {:?}", e);
+    }
+    report
+}
+
+/// Every active deal is checked against AmoCRM and Profitbase. The regular sync only sees
+/// leads updated since the watermark, so deleted leads and changed objects are caught here.
+pub async fn refresh(db: &Db) -> SyncReport {
+    let _guard = RUN_LOCK.lock().await;
+    let started = Local::now().naive_local();
+    let mut reports = vec![];
+    for project in projects() {
+        let mut report = ProjectReport::new(&project.name);
+        let mut result = refresh_project(db, project, &mut report).await;
+        if let Err(e) = &result
+            && is_unauthorized(project, e)
+        {
+            report = ProjectReport::new(&project.name);
+            result = match refresh_amo_token(db, project).await {
+                Ok(_) => refresh_project(db, project, &mut report).await,
+                Err(e) => Err(e),
             };
-            let house = house.parse::<i32>().unwrap_or(-1);
-
-            // soldAt
-            let created_on = DateTime::parse_from_str(
-                format!("{} +0000", p.sold_at).as_str(),
-                "%Y-%m-%d %H:%M %z",
-            )
-            .unwrap_or(Default::default())
-            .naive_local();
-            let attrs = p.attributes.clone();
-
-            Ok(DealForAdd {
-                deal_id,
-                project: p.project_name.clone(),
-                house,
-                object_type,
-                object: p.number.parse::<i32>()?,
-                facing: attrs.facing.unwrap_or("".to_string()),
-                created_on,
-            })
-        } else {
-            Err(Error::ProfitGetDataFailed)
         }
+        if let Err(e) = &result {
+            error!("[refresh] project {}: {:?}", project.name, e);
+        }
+        report.result = result.map(|_| vec![]);
+        reports.push(report);
+    }
+
+    let report = SyncReport { projects: reports };
+    if let Err(e) = db.save_sync_run(started, Local::now().naive_local(), Trigger::Refresh, &report).await {
+        error!("[refresh] unable to save run: {:?}", e);
+    }
+    report
+}
+
+/// Fetches the deal from Profitbase again and overwrites the stored object data
+pub async fn resync_deal(db: &Db, deal_id: u64) -> Result<DealForAdd> {
+    let stored = db.get_deal_by_id(deal_id).await?;
+    let project =
+        find_project(&stored.project).ok_or_else(|| Error::UnknownProject(stored.project.clone()))?;
+    let data = get_profit_data(deal_id, project).await?;
+    db.update_deal(&data).await?;
+    // A manual resync that went through is the way out of quarantine
+    db.release_deal(deal_id).await?;
+    let token = amo_access_token(db, project).await?;
+    sync_contacts(db, project, &token, deal_id, None).await;
+    Ok(data)
+}
+
+async fn sync_project(
+    db: &Db,
+    project: &Project,
+    full: bool,
+    report: &mut ProjectReport,
+) -> Result<Vec<DealForAdd>> {
+    let watermark = if full {
+        None
     } else {
-        Err(Error::ProfitGetDataFailed)
+        db.get_watermark(&project.name).await?
+    };
+    let filter = match watermark {
+        Some(updated_at) => format!("filter[updated_at][from]={}", updated_at),
+        None => "filter[created_at][from]=1600437670".to_string(),
+    };
+    debug!("[sync_project] {}: {}", project.name, filter);
+
+    let url = format!("{}/api/v4/leads?limit=250&with=contacts&{}", project.amo_url, filter);
+    let token = amo_access_token(db, project).await?;
+    let mut stream = pin!(lead_stream(url, &token, MAX_PAGES));
+
+    let saved_ids = db.read_deal_ids().await?;
+    let mut last_updated = watermark;
+    let mut leads = vec![];
+    let mut contacts = HashMap::new();
+    let mut known = vec![];
+    while let Some(lead) = stream.try_next().await? {
+        report.leads_seen += 1;
+        last_updated = last_updated.max(Some(lead.updated_at));
+        if saved_ids.contains(&lead.id) {
+            known.push(lead);
+        } else if project.filter.matches(&lead) {
+            leads.push(lead.id);
+            contacts.insert(lead.id, lead.embedded.contacts);
+        }
+    }
+    info!("[sync_project] {} matched leads {:?}", project.name, leads);
+
+    // Deals that failed on earlier runs are behind the watermark, so retry them explicitly
+    // Stored deals are there after a failed Profitbase check and go through `check_known` again
+    for lead in db.list_quarantined(&project.name).await? {
+        let saved = saved_ids.contains(&lead);
+        if leads.contains(&lead) || known.iter().any(|l| l.id == lead) {
+            continue;
+        }
+        match get_lead(&project.amo_url, &token, lead).await {
+            Ok(Some(l)) if saved => known.push(l),
+            Ok(Some(l)) if project.filter.matches(&l) && !project.filter.is_cancelled(&l) => {
+                contacts.insert(lead, l.embedded.contacts);
+                leads.push(lead);
+            }
+            Ok(None) if saved => {
+                let stored = db.get_deal_by_id(lead).await?;
+                db.release_deal(lead).await?;
+                if db.cancel_deal(lead).await? {
+                    report.cancelled.push(stored);
+                }
+            }
+            Ok(_) => {
+                info!("[sync_project] {} deal {} deleted or no longer DKP, released", project.name, lead);
+                db.release_deal(lead).await?;
+            }
+            Err(e) => error!("[sync_project] {} deal {}: {:?}", project.name, lead, e),
+        }
+    }
+    let leads: Vec<u64> = leads.into_iter().filter(|l| !saved_ids.contains(l)).collect();
+    let new_data = store_new_deals(db, project, &token, leads, &contacts, report).await?;
+    check_known(db, project, &token, known, report).await?;
+
+    // Move the cursor only after every new deal has been stored
+    if let Some(updated_at) = last_updated {
+        db.save_watermark(&project.name, updated_at).await?;
+    }
+
+    Ok(new_data)
+}
+
+async fn refresh_project(db: &Db, project: &Project, report: &mut ProjectReport) -> Result<()> {
+    let ids = db.list_active_deal_ids(&project.name).await?;
+    let token = amo_access_token(db, project).await?;
+    for batch in ids.chunks(REFRESH_BATCH) {
+        let filter: Vec<String> = batch.iter().map(|id| format!("filter[id][]={}", id)).collect();
+        let url = format!(
+            "{}/api/v4/leads?limit={}&with=contacts&{}",
+            project.amo_url,
+            REFRESH_BATCH,
+            filter.join("&")
+        );
+        let leads: Vec<Lead> = lead_stream(url, &token, MAX_PAGES)
+            .try_filter(|lead| std::future::ready(batch.contains(&lead.id)))
+            .try_collect()
+            .await?;
+        report.leads_seen += leads.len();
+
+        // AmoCRM leaves deleted leads out of the response
+        for &id in batch.iter().filter(|id| !leads.iter().any(|l| l.id == **id)) {
+            let stored = db.get_deal_by_id(id).await?;
+            if db.cancel_deal(id).await? {
+                info!("[refresh_project] {} deal {} deleted in AmoCRM", project.name, id);
+                report.cancelled.push(stored);
+            }
+        }
+        check_known(db, project, &token, leads, report).await?;
     }
+    Ok(())
 }
 
-#[derive(Deserialize)]
-struct AuthResponse {
-    pub access_token: String,
+async fn sync_lead_ids(
+    db: &Db,
+    project: &Project,
+    ids: &[u64],
+    report: &mut ProjectReport,
+) -> Result<Vec<DealForAdd>> {
+    let saved_ids = db.read_deal_ids().await?;
+    let token = amo_access_token(db, project).await?;
+    let mut leads = vec![];
+    let mut contacts = HashMap::new();
+    let mut known = vec![];
+    for &id in ids {
+        report.leads_seen += 1;
+        let lead = match get_lead(&project.amo_url, &token, id).await {
+            Ok(lead) => lead,
+            Err(e) if is_unauthorized(project, &e) => return Err(e),
+            Err(e) => {
+                // The lead keeps its updated_at, so the scheduled sync picks it up
+                error!("[sync_lead_ids] {} lead {}: {:?}", project.name, id, e);
+                continue;
+            }
+        };
+        match lead {
+            Some(lead) if saved_ids.contains(&id) => known.push(lead),
+            Some(lead) if project.filter.matches(&lead) => {
+                leads.push(id);
+                contacts.insert(id, lead.embedded.contacts);
+            }
+            Some(_) => {}
+            None if saved_ids.contains(&id) => {
+                let stored = db.get_deal_by_id(id).await?;
+                if db.cancel_deal(id).await? {
+                    report.cancelled.push(stored);
+                }
+            }
+            None => {}
+        }
+    }
+    info!("[sync_lead_ids] {} matched leads {:?}", project.name, leads);
+    let new_data = store_new_deals(db, project, &token, leads, &contacts, report).await?;
+    check_known(db, project, &token, known, report).await?;
+    Ok(new_data)
 }
-async fn get_profit_token(url: &str, api_key: &str) -> Result<String> {
-    let payload = json!({
-      "type": "api-app",
-      "credentials": {
-        "pb_api_key": api_key,
-      }
-    });
-    let client = Client::new()
-        .post(format!("{url}/authentication"))
-        .json(&payload);
 
-    let result = client.send().await?;
+/// Stored deals that came up again: cancel, restore or update them from Profitbase
+async fn check_known(
+    db: &Db,
+    project: &Project,
+    token: &str,
+    leads: Vec<Lead>,
+    report: &mut ProjectReport,
+) -> Result<()> {
+    for lead in leads {
+        let stored = db.get_deal_by_id(lead.id).await?;
+        if project.filter.is_cancelled(&lead) {
+            db.release_deal(lead.id).await?;
+            if db.cancel_deal(lead.id).await? {
+                info!("[check_known] {} deal {} cancelled", project.name, lead.id);
+                report.cancelled.push(stored);
+            }
+            continue;
+        }
+        sync_contacts(db, project, token, lead.id, Some(&lead.embedded.contacts)).await;
 
-    if result.status() == reqwest::StatusCode::OK {
-        let token = result.json::<AuthResponse>().await?.access_token;
-        debug!("Profitbase Token: {:?}", token);
-        return Ok(token);
+        let fresh = match get_profit_data(lead.id, project).await {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                // The watermark moves on, so the quarantine makes the next sync check it again
+                let reason = e.to_string();
+                error!("[check_known] {} deal {}: {}", project.name, lead.id, reason);
+                let attempts = db.quarantine_deal(lead.id, &project.name, &reason).await?;
+                report.skipped.push(SkippedDeal {
+                    deal_id: lead.id,
+                    reason,
+                    attempts,
+                });
+                continue;
+            }
+        };
+        db.release_deal(lead.id).await?;
+        let mut changes = diff(&stored, &fresh);
+        if stored.cancelled_on.is_some() {
+            db.restore_deal(lead.id).await?;
+            changes.insert(0, "сделка восстановлена".to_string());
+        }
+        let updated = !changes.is_empty() || details_differ(&stored, &fresh);
+        if updated && !project.object_types.contains(&fresh.object_type) {
+            report.unlisted.push((fresh.deal_id, fresh.object_type.clone()));
+        }
+        if !changes.is_empty() {
+            db.update_deal(&fresh).await?;
+            report.changed.push(DealChange {
+                deal: fresh,
+                changes,
+            });
+        } else if updated {
+            // Area, price and the rest are refreshed quietly, this also fills them in for older rows
+            db.update_deal(&fresh).await?;
+        }
     }
+    Ok(())
+}
 
-    Err(Error::ProfitAuthFailed)
+fn details_differ(stored: &HouseData, fresh: &DealForAdd) -> bool {
+    stored.property_id != fresh.property_id
+        || stored.rooms != fresh.rooms
+        || stored.floor != fresh.floor
+        || stored.section != fresh.section
+        || stored.area != fresh.area
+        || stored.price != fresh.price
+        || stored.building() != fresh.building()
+}
+
+fn diff(stored: &HouseData, fresh: &DealForAdd) -> Vec<String> {
+    let mut changes = vec![];
+    // Older rows with an unknown house are filled in quietly
+    if stored.building() != fresh.building() && stored.house != UNKNOWN_HOUSE {
+        changes.push(format!("дом № {} → {}", stored.building().label(), fresh.building().label()));
+    }
+    if stored.object_type != fresh.object_type {
+        changes.push(format!("тип объекта {} → {}", stored.object_type, fresh.object_type));
+    }
+    if stored.object != fresh.object {
+        changes.push(format!("№ {} → {}", stored.object, fresh.object));
+    }
+    if stored.facing != fresh.facing {
+        changes.push(format!("тип отделки «{}» → «{}»", stored.facing, fresh.facing));
+    }
+    changes
+}
+
+/// Profitbase data for leads missing in the database, failures go to quarantine.
+/// `contacts` has the contact ids of the streamed leads, quarantined ones are fetched again.
+async fn store_new_deals(
+    db: &Db,
+    project: &Project,
+    token: &str,
+    leads: Vec<u64>,
+    contacts: &HashMap<u64, Vec<ContactRef>>,
+    report: &mut ProjectReport,
+) -> Result<Vec<DealForAdd>> {
+    let mut new_data: Vec<DealForAdd> = vec![];
+    if !leads.is_empty() {
+        // Fail the project early instead of quarantining every deal on bad credentials
+        profit_token(project).await?;
+        for lead in leads {
+            match store_deal(db, lead, project).await {
+                Ok(full_data) => {
+                    db.release_deal(lead).await?;
+                    if !project.object_types.contains(&full_data.object_type) {
+                        report.unlisted.push((lead, full_data.object_type.clone()));
+                    }
+                    sync_contacts(db, project, token, lead, contacts.get(&lead).map(Vec::as_slice)).await;
+                    new_data.push(full_data);
+                }
+                Err(e) => {
+                    let reason = e.to_string();
+                    error!("[sync_project] {} deal {}: {}", project.name, lead, reason);
+                    let attempts = db.quarantine_deal(lead, &project.name, &reason).await?;
+                    report.skipped.push(SkippedDeal {
+                        deal_id: lead,
+                        reason,
+                        attempts,
+                    });
+                }
+            }
+        }
+    }
+    Ok(new_data)
+}
+
+/// Contacts are secondary, a failure is logged and retried on the next update of the lead
+async fn sync_contacts(db: &Db, project: &Project, token: &str, deal_id: u64, refs: Option<&[ContactRef]>) {
+    let result = async {
+        let refs = match refs {
+            Some(refs) => refs.to_vec(),
+            None => get_lead(&project.amo_url, token, deal_id)
+                .await?
+                .map(|l| l.embedded.contacts)
+                .unwrap_or_default(),
+        };
+        let contacts = fetch_contacts(&project.amo_url, token, &refs).await?;
+        db.save_contacts(deal_id, &contacts).await
+    };
+    if let Err(e) = result.await {
+        error!("[sync_contacts] {} deal {}: {:?}", project.name, deal_id, e);
+    }
+}
+
+async fn store_deal(db: &Db, deal_id: u64, project: &Project) -> Result<DealForAdd> {
+    let full_data = get_profit_data(deal_id, project).await?;
+    db.create_deal(&full_data).await?;
+    Ok(full_data)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    #[test]
-    fn parse_date() {
-        let str_date = "2025-03-12 04:38 +0000";
-        let res = DateTime::parse_from_str(str_date, "%Y-%m-%d %H:%M %z");
-        println!("{:?}", res);
-        assert!(res.is_ok());
+    use crate::model::filter::LOST_STATUS;
+    use crate::model::{test_db, test_deal};
+    use serde_json::json;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// `failing` has no Profitbase data, `lost` leads are closed in AmoCRM
+    async fn mock_servers(failing: u64, lost: &[u64]) -> (MockServer, Project) {
+        let server = MockServer::start().await;
+        let leads: Vec<_> = [3001, failing]
+            .iter()
+            .chain(lost)
+            .map(|id| {
+                let status_id = if lost.contains(id) { LOST_STATUS } else { 58000001 };
+                let contacts = if *id == 3001 { json!([{ "id": 501, "is_main": true }]) } else { json!([]) };
+                json!({
+                    "id": id, "name": "", "created_at": 1741754280, "updated_at": 1741766400,
+                    "pipeline_id": 7000001, "status_id": status_id,
+                    "custom_fields_values": [{ "field_id": 1631153, "field_name": "Тип договора", "values": [{ "value": "ДКП", "enum_id": 4661181 }] }],
+                    "_embedded": { "contacts": contacts }
+                })
+            })
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/api/v4/leads"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "_embedded": { "leads": leads } })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/contacts"))
+            .and(query_param("filter[id][]", "501"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "_embedded": { "contacts": [{
+                "id": 501, "name": "Иванов Иван",
+                "custom_fields_values": [{ "field_id": 1, "field_name": "Телефон", "field_code": "PHONE", "values": [{ "value": "+79000000001" }] }]
+            }] } })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/authentication"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "access_token": "pb" })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/property/deal/3001"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "success",
+                "data": [{
+                    "number": "57", "houseName": "Дом №3", "projectName": "DNS Сити",
+                    "attributes": { "facing": "Чистовая" }, "soldAt": "2025-03-12 04:38"
+                }]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/property/deal/{}", failing)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "status": "success", "data": [] })))
+            .mount(&server)
+            .await;
+
+        let project: Project = toml::from_str(&format!(
+            r#"
+            name = "DNS Сити"
+            amo_url = "{0}"
+            amo_token = "amo"
+            prof_url = "{0}"
+            prof_api_key = "key"
+            object_types = ["Квартиры"]
+            filter = {{ field_id = 1631153, enum_ids = [4661181] }}
+            "#,
+            server.uri()
+        ))
+        .unwrap();
+        (server, project)
+    }
+
+    #[tokio::test]
+    async fn quarantines_failed_deal() {
+        let db = test_db().await;
+        let (_server, project) = mock_servers(3002, &[]).await;
+
+        let mut report = ProjectReport::new(&project.name);
+        let deals = sync_project(&db, &project, false, &mut report).await.unwrap();
+        assert_eq!(deals.iter().map(|d| d.deal_id).collect::<Vec<_>>(), vec![3001]);
+        assert_eq!(report.leads_seen, 2);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].deal_id, 3002);
+        assert_eq!(db.list_quarantined("DNS Сити").await.unwrap(), vec![3002]);
+        let contacts = db.list_contacts(3001).await.unwrap();
+        assert_eq!(contacts[0].phone.as_deref(), Some("+79000000001"));
+
+        // Behind the watermark now, but still retried
+        let mut report = ProjectReport::new(&project.name);
+        sync_project(&db, &project, false, &mut report).await.unwrap();
+        assert_eq!(report.skipped[0].attempts, 2);
+        let summary = SyncReport { projects: vec![report] }.skipped_summary();
+        assert!(summary.is_none());
+        assert_eq!(db.read_deal_ids().await.unwrap(), vec![3001]);
+
+        // A lead deleted in AmoCRM since is released instead of retried forever
+        db.quarantine_deal(3003, "DNS Сити", "NoProfitData").await.unwrap();
+        sync_project(&db, &project, false, &mut ProjectReport::new(&project.name)).await.unwrap();
+        assert_eq!(db.list_quarantined("DNS Сити").await.unwrap(), vec![3002]);
+    }
+
+    #[tokio::test]
+    async fn updates_and_cancels_stored_deals() {
+        let db = test_db().await;
+        let (_server, project) = mock_servers(3002, &[3005]).await;
+        for (deal_id, house) in [(3001, 2), (3005, 3)] {
+            db.create_deal(&DealForAdd { house, ..test_deal(deal_id) }).await.unwrap();
+        }
+
+        let mut report = ProjectReport::new(&project.name);
+        sync_project(&db, &project, false, &mut report).await.unwrap();
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(
+            report.changed[0].changes,
+            vec!["дом № 2 → 3", "тип отделки «» → «Чистовая»"]
+        );
+        assert_eq!(report.cancelled.len(), 1);
+        assert_eq!(report.cancelled[0].deal_id, 3005);
+
+        // The cancelled deal stays in the table but not in the bot
+        let found: Vec<u64> = db.find_deals(3, 57).await.unwrap().iter().map(|d| d.deal_id).collect();
+        assert_eq!(found, vec![3001]);
+        assert!(db.get_deal_by_id(3005).await.unwrap().cancelled_on.is_some());
+
+        let digest = SyncReport { projects: vec![report] }.digest().unwrap();
+        assert!(digest.contains("Изменена сделка 3001: дом № 2 → 3"));
+        assert!(digest.contains("Отменена сделка 3005: дом № 3 Квартиры № 57"));
+
+        // Nothing changed since, so nothing to report
+        let mut report = ProjectReport::new(&project.name);
+        sync_project(&db, &project, false, &mut report).await.unwrap();
+        assert!(SyncReport { projects: vec![report] }.digest().is_none());
+    }
+
+    #[tokio::test]
+    async fn refresh_checks_stored_deals() {
+        let db = test_db().await;
+        let (_server, project) = mock_servers(3002, &[]).await;
+        // 3001 was stored with an unknown house, 3009 is deleted in AmoCRM,
+        // 3002 has no Profitbase data anymore
+        for (deal_id, house) in [(3001, UNKNOWN_HOUSE), (3002, 3), (3009, 3)] {
+            let deal = DealForAdd {
+                house,
+                facing: "Чистовая".to_string(),
+                ..test_deal(deal_id)
+            };
+            db.create_deal(&deal).await.unwrap();
+        }
+
+        let mut report = ProjectReport::new(&project.name);
+        refresh_project(&db, &project, &mut report).await.unwrap();
+        assert_eq!(report.leads_seen, 2);
+        assert_eq!(report.cancelled.iter().map(|d| d.deal_id).collect::<Vec<_>>(), vec![3009]);
+        assert!(report.changed.is_empty());
+        assert!(report.unlisted.is_empty());
+        assert_eq!(db.get_deal_by_id(3001).await.unwrap().house, 3);
+        assert_eq!(report.skipped.iter().map(|s| s.deal_id).collect::<Vec<_>>(), vec![3002]);
+        assert_eq!(db.list_quarantined("DNS Сити").await.unwrap(), vec![3002]);
+        assert_eq!(db.list_active_deal_ids("DNS Сити").await.unwrap(), vec![3001, 3002]);
+
+        // Flats missing from the projects file are still stored, but the admin is told
+        let db = test_db().await;
+        let mut project = project;
+        project.object_types = vec!["Кладовки".to_string()];
+        let mut report = ProjectReport::new(&project.name);
+        sync_project(&db, &project, false, &mut report).await.unwrap();
+        assert_eq!(report.unlisted, vec![(3001, "Квартиры".to_string())]);
+        let summary = SyncReport { projects: vec![report] }.unlisted_summary().unwrap();
+        assert!(summary.contains("DNS Сити, «Квартиры»: 3001\n"));
     }
 }
@@ -0,0 +1,186 @@
+use crate::model::sync::SyncReport;
+use crate::model::Db;
+use crate::Result;
+use log::debug;
+use sqlx::types::chrono::NaiveDateTime;
+use sqlx::FromRow;
+use std::fmt::{Display, Formatter};
+
+/// Ids listed per project in the history, the rest are counted
+const MAX_IDS_SHOWN: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Trigger {
+    Cron,
+    /// Nightly recheck of the stored deals
+    Refresh,
+    Webhook,
+    User(i64),
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trigger::Cron => write!(f, "cron"),
+            Trigger::Refresh => write!(f, "refresh"),
+            Trigger::Webhook => write!(f, "webhook"),
+            Trigger::User(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+#[derive(FromRow, Debug)]
+pub struct SyncRun {
+    pub id: i64,
+    pub started_at: NaiveDateTime,
+    pub finished_at: NaiveDateTime,
+    pub trigger: String,
+    #[sqlx(skip)]
+    pub projects: Vec<SyncRunProject>,
+}
+
+#[derive(FromRow, Debug)]
+pub struct SyncRunProject {
+    pub project: String,
+    pub leads_seen: i64,
+    pub new_deals: String,
+    pub error: Option<String>,
+}
+
+impl Db {
+    pub async fn save_sync_run(
+        &self,
+        started_at: NaiveDateTime,
+        finished_at: NaiveDateTime,
+        trigger: Trigger,
+        report: &SyncReport,
+    ) -> Result<i64> {
+        let mut tx = self.db.begin().await?;
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO sync_run (started_at, finished_at, trigger) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(started_at)
+        .bind(finished_at)
+        .bind(trigger.to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for p in &report.projects {
+            let (new_deals, error) = match &p.result {
+                Ok(deals) => {
+                    let ids: Vec<String> = deals.iter().map(|d| d.deal_id.to_string()).collect();
+                    (ids.join(" "), None)
+                }
+                Err(e) => (String::new(), Some(e.to_string())),
+            };
+            sqlx::query(
+                r#"
+                INSERT INTO sync_run_project (run_id, project, leads_seen, new_deals, error)
+                VALUES ($1, $2, $3, $4, $5)"#,
+            )
+            .bind(id)
+            .bind(&p.project)
+            .bind(p.leads_seen as i64)
+            .bind(new_deals)
+            .bind(error)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        debug!("[save_sync_run] run {} by {}", id, trigger);
+        Ok(id)
+    }
+
+    /// Latest runs first
+    pub async fn list_sync_runs(&self, limit: u32) -> Result<Vec<SyncRun>> {
+        let mut runs: Vec<SyncRun> = sqlx::query_as("SELECT * FROM sync_run ORDER BY id DESC LIMIT $1")
+            .bind(limit)
+            .fetch_all(&self.db)
+            .await?;
+        for run in &mut runs {
+            run.projects = sqlx::query_as(
+                "SELECT project, leads_seen, new_deals, error FROM sync_run_project WHERE run_id = $1",
+            )
+            .bind(run.id)
+            .fetch_all(&self.db)
+            .await?;
+        }
+        Ok(runs)
+    }
+}
+
+impl Display for SyncRun {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let trigger = match self.trigger.as_str() {
+            "cron" => "по расписанию".to_string(),
+            "refresh" => "сверка сделок".to_string(),
+            "webhook" => "вебхук AmoCRM".to_string(),
+            t => format!("пользователь {}", t),
+        };
+        let duration = (self.finished_at - self.started_at).num_milliseconds() as f64 / 1000.0;
+        writeln!(
+            f,
+            "{}, {}, {:.1} с",
+            self.started_at.format("%d.%m.%Y %H:%M:%S"),
+            trigger,
+            duration
+        )?;
+        for p in &self.projects {
+            match &p.error {
+                Some(e) => writeln!(f, "  {}: ошибка {}", p.project, e)?,
+                None => {
+                    let ids: Vec<&str> = p.new_deals.split_whitespace().collect();
+                    write!(f, "  {}: лидов {}, новых сделок {}", p.project, p.leads_seen, ids.len())?;
+                    if !ids.is_empty() {
+                        write!(f, " ({}", ids[..ids.len().min(MAX_IDS_SHOWN)].join(", "))?;
+                        if ids.len() > MAX_IDS_SHOWN {
+                            write!(f, ", …")?;
+                        }
+                        write!(f, ")")?;
+                    }
+                    writeln!(f)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::model::sync::ProjectReport;
+    use crate::model::{test_db, test_deal};
+
+    #[tokio::test]
+    async fn save_and_list() {
+        let db = test_db().await;
+        let deal = test_deal(3001);
+        let report = SyncReport {
+            projects: vec![
+                ProjectReport {
+                    leads_seen: 12,
+                    result: Ok(vec![deal]),
+                    ..ProjectReport::new("DNS Сити")
+                },
+                ProjectReport {
+                    result: Err(Error::ProfitAuthFailed),
+                    ..ProjectReport::new("ЖК Формат")
+                },
+            ],
+        };
+        let started = NaiveDateTime::default();
+        let finished = started + chrono::Duration::milliseconds(1500);
+        db.save_sync_run(started, finished, Trigger::Cron, &report).await.unwrap();
+        db.save_sync_run(started, finished, Trigger::User(42), &report).await.unwrap();
+
+        let runs = db.list_sync_runs(1).await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].trigger, "42");
+        assert_eq!(
+            runs[0].to_string(),
+            "01.01.1970 00:00:00, пользователь 42, 1.5 с\n  DNS Сити: лидов 12, новых сделок 1 (3001)\n  ЖК Формат: ошибка ProfitAuthFailed\n"
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use crate::model::Db;
+use crate::Result;
+use log::debug;
+
+impl Db {
+    /// `updated_at` of the newest lead seen by the last successful sync
+    pub async fn get_watermark(&self, project: &str) -> Result<Option<i64>> {
+        let row: Option<(i64,)> = sqlx::query_as("SELECT updated_at FROM sync_state WHERE project = $1")
+            .bind(project)
+            .fetch_optional(&self.db)
+            .await?;
+        Ok(row.map(|r| r.0))
+    }
+
+    pub async fn save_watermark(&self, project: &str, updated_at: i64) -> Result<()> {
+        debug!("[save_watermark] {} -> {}", project, updated_at);
+        sqlx::query(
+            r#"
+            INSERT INTO sync_state (project, updated_at) VALUES ($1, $2)
+            ON CONFLICT(project) DO UPDATE SET updated_at = excluded.updated_at"#,
+        )
+        .bind(project)
+        .bind(updated_at)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use crate::model::Db;
+use crate::Result;
+use log::debug;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sqlx::FromRow;
+use std::str::FromStr;
+
+/// Ordered by privileges, every role can do what the lower ones can
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Manager,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Manager => "manager",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        [Role::Viewer, Role::Manager, Role::Admin]
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or(())
+    }
+}
+
+#[derive(FromRow, Debug)]
+pub struct UserData {
+    pub user_id: i64,
+    pub name: String,
+    pub role: Role,
+}
+
+impl Db {
+    pub async fn get_role(&self, user_id: i64) -> Result<Option<Role>> {
+        let row: Option<(Role,)> = sqlx::query_as("SELECT role FROM users WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?;
+        Ok(row.map(|r| r.0))
+    }
+
+    pub async fn list_users(&self) -> Result<Vec<UserData>> {
+        let rows = sqlx::query_as("SELECT user_id, name, role FROM users ORDER BY role, name")
+            .fetch_all(&self.db)
+            .await?;
+        Ok(rows)
+    }
+
+    pub async fn revoke_user(&self, user_id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM users WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Single-use token for a `/start <token>` deep link
+    pub async fn create_invite(&self, role: Role, created_by: i64) -> Result<String> {
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+        sqlx::query("INSERT INTO invite (token, role, created_by) VALUES ($1, $2, $3)")
+            .bind(&token)
+            .bind(role)
+            .bind(created_by)
+            .execute(&self.db)
+            .await?;
+        Ok(token)
+    }
+
+    /// Grants the invite role, an existing higher role is kept.
+    /// Returns the user's role, None for an unknown, used or week-old token
+    pub async fn accept_invite(&self, token: &str, user_id: i64, name: &str) -> Result<Option<Role>> {
+        let mut tx = self.db.begin().await?;
+        let invite: Option<(Role, i64)> = sqlx::query_as(
+            r#"
+            UPDATE invite SET used_by = $1
+            WHERE token = $2 AND used_by IS NULL AND created_on > datetime('now', 'localtime', '-7 days')
+            RETURNING role, created_by"#,
+        )
+        .bind(user_id)
+        .bind(token)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some((invite_role, invited_by)) = invite else {
+            return Ok(None);
+        };
+        let current: Option<(Role,)> = sqlx::query_as("SELECT role FROM users WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let role = current.map_or(invite_role, |(current,)| current.max(invite_role));
+
+        debug!("[accept_invite] {} ({}) as {:?}", name, user_id, role);
+        sqlx::query(
+            r#"
+            INSERT INTO users (user_id, name, role, invited_by) VALUES ($1, $2, $3, $4)
+            ON CONFLICT(user_id) DO UPDATE SET name = excluded.name, role = excluded.role"#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(role)
+        .bind(invited_by)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some(role))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_db;
+
+    #[tokio::test]
+    async fn invite_is_single_use() {
+        let db = test_db().await;
+        assert_eq!(db.get_role(42).await.unwrap(), None);
+
+        let token = db.create_invite(Role::Manager, 1).await.unwrap();
+        assert_eq!(db.accept_invite("wrong", 42, "Иван").await.unwrap(), None);
+        assert_eq!(db.accept_invite(&token, 42, "Иван").await.unwrap(), Some(Role::Manager));
+        assert_eq!(db.accept_invite(&token, 43, "Пётр").await.unwrap(), None);
+
+        assert_eq!(db.get_role(42).await.unwrap(), Some(Role::Manager));
+        assert_eq!(db.get_role(43).await.unwrap(), None);
+        assert!(Role::Admin > Role::Manager && Role::Manager > Role::Viewer);
+    }
+
+    #[tokio::test]
+    async fn invite_keeps_higher_role() {
+        let db = test_db().await;
+        let token = db.create_invite(Role::Admin, 1).await.unwrap();
+        db.accept_invite(&token, 42, "Иван").await.unwrap();
+
+        let token = db.create_invite(Role::Viewer, 1).await.unwrap();
+        assert_eq!(db.accept_invite(&token, 42, "Иван").await.unwrap(), Some(Role::Admin));
+        assert_eq!(db.get_role(42).await.unwrap(), Some(Role::Admin));
+
+        let token = db.create_invite(Role::Manager, 1).await.unwrap();
+        db.accept_invite(&token, 43, "Пётр").await.unwrap();
+        let token = db.create_invite(Role::Admin, 1).await.unwrap();
+        assert_eq!(db.accept_invite(&token, 43, "Пётр").await.unwrap(), Some(Role::Admin));
+    }
+
+    #[tokio::test]
+    async fn revoke() {
+        let db = test_db().await;
+        let token = db.create_invite(Role::Viewer, 1).await.unwrap();
+        db.accept_invite(&token, 42, "Иван").await.unwrap();
+        let users = db.list_users().await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!((users[0].user_id, users[0].role), (42, Role::Viewer));
+
+        assert!(db.revoke_user(42).await.unwrap());
+        assert!(!db.revoke_user(42).await.unwrap());
+        assert_eq!(db.get_role(42).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,61 @@
+use log::error;
+use teloxide::prelude::*;
+
+/// Telegram rejects messages over 4096 characters, keep a margin
+const MAX_MESSAGE_LEN: usize = 4000;
+
+/// Splits on line breaks, a single line over the limit is cut by characters
+pub fn split_message(text: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut len = 0;
+    for line in text.split_inclusive('\n') {
+        let line_len = line.chars().count();
+        if len + line_len > MAX_MESSAGE_LEN && !current.is_empty() {
+            parts.push(std::mem::take(&mut current));
+            len = 0;
+        }
+        if line_len > MAX_MESSAGE_LEN {
+            let chars: Vec<char> = line.chars().collect();
+            for chunk in chars.chunks(MAX_MESSAGE_LEN) {
+                parts.push(chunk.iter().collect());
+            }
+            continue;
+        }
+        current.push_str(line);
+        len += line_len;
+    }
+    if !current.trim().is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+/// For the scheduled jobs: a failed message is logged and must not stop the job
+pub async fn notify(bot: &Bot, chat_id: ChatId, text: &str) {
+    for part in split_message(text) {
+        if let Err(e) = bot.send_message(chat_id, part).await {
+            error!("[notify] unable to send message to {}: {:?}", chat_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        assert_eq!(split_message("Сроки передачи\n"), vec!["Сроки передачи\n"]);
+        assert!(split_message("").is_empty());
+
+        let line = format!("{}\n", "д".repeat(99));
+        let parts = split_message(&line.repeat(100));
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|p| p.chars().count() <= MAX_MESSAGE_LEN && p.ends_with('\n')));
+        assert_eq!(parts.concat(), line.repeat(100));
+
+        let parts = split_message(&"д".repeat(MAX_MESSAGE_LEN + 1));
+        assert_eq!(parts.iter().map(|p| p.chars().count()).collect::<Vec<_>>(), vec![MAX_MESSAGE_LEN, 1]);
+    }
+}
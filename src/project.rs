@@ -0,0 +1,181 @@
+use crate::card::{default_template, CardField};
+use crate::config::config;
+use crate::deadline::Period;
+use crate::error::Error;
+use crate::model::filter::DealFilter;
+use crate::Result;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+
+pub fn projects() -> &'static [Project] {
+    static INSTANCE: OnceLock<Vec<Project>> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| {
+        load_from_file(&config().PROJECTS_FILE).unwrap_or_else(|err| {
+            panic!("FATAL - WHILE LOADING Projects -cause: {:?}", err);
+        })
+    })
+}
+
+pub fn find_project(name: &str) -> Option<&'static Project> {
+    projects().iter().find(|p| p.name == name)
+}
+
+pub const KEY_LEN: usize = 8;
+
+/// Stable short id of a project or object type name for callback data.
+/// FNV-1a, the std hasher may change between Rust releases.
+pub fn short_key(name: &str) -> String {
+    let hash = name
+        .bytes()
+        .fold(0x811c9dc5u32, |hash, b| (hash ^ b as u32).wrapping_mul(0x01000193));
+    format!("{:0width$x}", hash, width = KEY_LEN)
+}
+
+pub fn find_project_by_key(key: &str) -> Option<&'static Project> {
+    projects().iter().find(|p| short_key(&p.name) == key)
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Project {
+    /// Display name, also stored in `deal.project`
+    pub name: String,
+    // -- AmoCRM
+    /// Account base url, e.g. `https://city.amocrm.ru`, the API paths are appended to it
+    pub amo_url: String,
+    /// Long-lived token, not used when `oauth` is set
+    #[serde(default)]
+    pub amo_token: String,
+    pub oauth: Option<AmoOAuth>,
+    pub filter: DealFilter,
+    // -- Profitbase
+    pub prof_url: String,
+    pub prof_api_key: String,
+    // -- Bot menu
+    pub object_types: Vec<String>,
+    // -- Handover deadline
+    #[serde(default)]
+    pub handover: Period,
+    /// Overrides `handover` for the given object types
+    #[serde(default)]
+    pub handover_by_type: HashMap<String, Period>,
+    /// Object card fields by object type
+    #[serde(default)]
+    pub card_by_type: HashMap<String, Vec<CardField>>,
+}
+
+/// AmoCRM integration credentials
+#[derive(Deserialize, Debug, Clone)]
+pub struct AmoOAuth {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    /// Used once, the rotated tokens are kept in the database
+    pub refresh_token: Option<String>,
+}
+
+impl Project {
+    pub fn period(&self, object_type: &str) -> Period {
+        self.handover_by_type
+            .get(object_type)
+            .copied()
+            .unwrap_or(self.handover)
+    }
+
+    pub fn find_object_type(&self, key: &str) -> Option<&str> {
+        self.object_types
+            .iter()
+            .find(|t| short_key(t) == key)
+            .map(String::as_str)
+    }
+
+    pub fn card(&self, object_type: &str) -> Vec<CardField> {
+        self.card_by_type
+            .get(object_type)
+            .cloned()
+            .unwrap_or_else(|| default_template(object_type))
+    }
+}
+
+#[derive(Deserialize)]
+struct Registry {
+    project: Vec<Project>,
+}
+
+fn load_from_file(path: &str) -> Result<Vec<Project>> {
+    let content = fs::read_to_string(path).map_err(Error::ProjectsFileRead)?;
+    parse(&content)
+}
+
+fn parse(content: &str) -> Result<Vec<Project>> {
+    let mut registry: Registry = toml::from_str(content).map_err(Error::ProjectsFileParse)?;
+    for project in &mut registry.project {
+        project.amo_url = base_url(&project.amo_url)
+            .ok_or_else(|| Error::ProjectsFileInvalid(format!("{}: amo_url", project.name)))?;
+    }
+    Ok(registry.project)
+}
+
+/// The old env setting was a full leads query, its filter would be silently dropped
+fn base_url(url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    if !matches!(parsed.path(), "" | "/") || parsed.query().is_some() {
+        return None;
+    }
+    Some(url.trim_end_matches('/').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parse_example() {
+        let projects = parse(include_str!("../projects.example.toml")).unwrap();
+        assert_eq!(projects.len(), 2);
+        assert_eq!(projects[1].name, "ЖК Формат");
+        assert!(projects[0].oauth.is_none());
+        assert_eq!(projects[1].oauth.as_ref().unwrap().client_id, "<integration id>");
+        assert_eq!(projects[0].object_types, vec!["Квартиры", "Кладовки"]);
+        assert_eq!(projects[0].filter.enum_ids, vec![4661181]);
+        assert_eq!(projects[0].period("Квартиры"), Period::default());
+        assert_eq!(
+            projects[0].card("Кладовки"),
+            vec![CardField::Section, CardField::Area, CardField::Price]
+        );
+        assert_eq!(projects[1].card("Квартиры"), default_template("Квартиры"));
+        assert_eq!(
+            projects[0].period("Кладовки"),
+            Period {
+                days: 10,
+                business_days: true
+            }
+        );
+    }
+
+    #[test]
+    fn keys() {
+        let projects = parse(include_str!("../projects.example.toml")).unwrap();
+        assert_eq!(short_key("DNS Сити"), short_key("DNS Сити"));
+        assert_ne!(short_key(&projects[0].name), short_key(&projects[1].name));
+        assert_eq!(short_key("Квартиры").len(), KEY_LEN);
+        assert_eq!(projects[0].find_object_type(&short_key("Кладовки")), Some("Кладовки"));
+        assert_eq!(projects[0].find_object_type(&short_key("Паркинг")), None);
+    }
+
+    #[test]
+    fn rejects_query_url() {
+        let example = include_str!("../projects.example.toml");
+        let with_slash = example.replace("https://city.amocrm.ru", "https://city.amocrm.ru/");
+        assert_eq!(parse(&with_slash).unwrap()[0].amo_url, "https://city.amocrm.ru");
+
+        for url in ["https://city.amocrm.ru/api/v4/leads?limit=250", "city"] {
+            let content = example.replace("https://city.amocrm.ru", url);
+            assert!(matches!(parse(&content), Err(Error::ProjectsFileInvalid(_))), "{}", url);
+        }
+        let content = example.replace("days = 30, business_days", "days = -30, business_days");
+        assert!(matches!(parse(&content), Err(Error::ProjectsFileParse(_))));
+    }
+}
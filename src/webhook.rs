@@ -0,0 +1,175 @@
+use crate::config::config;
+use crate::model::sync::sync_leads;
+use crate::model::Db;
+use crate::notify::notify;
+use crate::project::{projects, Project};
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use log::{error, info, warn};
+use reqwest::Url;
+use teloxide::types::ChatId;
+use teloxide::Bot;
+use tokio::net::TcpListener;
+
+#[derive(Clone)]
+struct AppState {
+    bot: Bot,
+    db: Db,
+    secret: String,
+}
+
+/// AmoCRM webhook listener, the scheduled sync keeps running as a fallback.
+/// The integration posts to `http://<WEBHOOK_ADDR>/amocrm/<WEBHOOK_SECRET>`.
+pub fn serve(bot: Bot, db: Db) {
+    let (Some(addr), Some(secret)) = (&config().WEBHOOK_ADDR, &config().WEBHOOK_SECRET) else {
+        info!("WEBHOOK_ADDR or WEBHOOK_SECRET is not set, AmoCRM webhooks are off");
+        return;
+    };
+    let app = Router::new()
+        .route("/amocrm/{secret}", post(receive))
+        .with_state(AppState {
+            bot: bot.clone(),
+            db,
+            secret: secret.clone(),
+        });
+    tokio::spawn(async move {
+        // The bot keeps working without webhooks, so tell the admin instead of panicking the task
+        let result = match TcpListener::bind(addr).await {
+            Ok(listener) => {
+                info!("Listening for AmoCRM webhooks on {}", addr);
+                axum::serve(listener, app).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("[webhook] listener on {} failed: {:?}", addr, e);
+            let text = format!("Вебхуки AmoCRM отключены, {}: {}\nСделки загружаются только по расписанию", addr, e);
+            notify(&bot, ChatId(config().ADMIN_ID), &text).await;
+        }
+    });
+}
+
+async fn receive(State(state): State<AppState>, Path(secret): Path<String>, body: Bytes) -> StatusCode {
+    if secret != state.secret {
+        warn!("[webhook] wrong secret");
+        return StatusCode::NOT_FOUND;
+    }
+    let Some(event) = parse_event(&body) else {
+        warn!("[webhook] unexpected payload");
+        return StatusCode::BAD_REQUEST;
+    };
+    let Some(project) = projects()
+        .iter()
+        .find(|p| subdomain(&p.amo_url).as_deref() == Some(event.subdomain.as_str()))
+    else {
+        warn!("[webhook] unknown account {}", event.subdomain);
+        return StatusCode::FORBIDDEN;
+    };
+
+    // AmoCRM waits only a couple of seconds for the answer
+    if !event.lead_ids.is_empty() {
+        tokio::spawn(process(state.bot, state.db, project, event.lead_ids));
+    }
+    StatusCode::OK
+}
+
+async fn process(bot: Bot, db: Db, project: &'static Project, lead_ids: Vec<u64>) {
+    let report = sync_leads(&db, project, &lead_ids).await;
+    for e in report.admin_notices() {
+        notify(&bot, ChatId(config().ADMIN_ID), &e).await;
+    }
+    let new_deals = report.have_data().then(|| report.to_string());
+    for text in new_deals.into_iter().chain(report.digest()) {
+        notify(&bot, ChatId(config().TG_GROUP_ID), &text).await;
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Event {
+    subdomain: String,
+    lead_ids: Vec<u64>,
+}
+
+/// Form encoded body, e.g. `account[subdomain]=city&leads[add][0][id]=3001`
+fn parse_event(body: &[u8]) -> Option<Event> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_bytes(body).ok()?;
+    let subdomain = pairs
+        .iter()
+        .find(|(k, _)| k == "account[subdomain]")
+        .map(|(_, v)| v.clone())?;
+
+    let mut lead_ids = vec![];
+    for (key, value) in &pairs {
+        let Some((action, index)) = key
+            .strip_prefix("leads[")
+            .and_then(|k| k.strip_suffix("][id]"))
+            .and_then(|k| k.split_once("]["))
+        else {
+            continue;
+        };
+        if matches!(action, "add" | "update" | "status")
+            && index.parse::<usize>().is_ok()
+            && let Ok(id) = value.parse::<u64>()
+            && !lead_ids.contains(&id)
+        {
+            lead_ids.push(id);
+        }
+    }
+    Some(Event { subdomain, lead_ids })
+}
+
+/// `https://city.amocrm.ru` -> `city`
+fn subdomain(amo_url: &str) -> Option<String> {
+    let url = Url::parse(amo_url).ok()?;
+    let host = url.host_str()?;
+    host.split('.').next().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_payload() {
+        // Body, subdomain and lead ids, None when the payload is rejected
+        let cases: &[(&str, Option<&str>, &[u64])] = &[
+            (
+                "account%5Bsubdomain%5D=city&account%5Bid%5D=1&leads%5Badd%5D%5B0%5D%5Bid%5D=3001\
+                &leads%5Badd%5D%5B0%5D%5Bname%5D=x&leads%5Badd%5D%5B1%5D%5Bid%5D=3002",
+                Some("city"),
+                &[3001, 3002],
+            ),
+            (
+                "leads[update][0][id]=3001&leads[status][0][id]=3001&leads[update][0][status_id]=142\
+                &account[subdomain]=format",
+                Some("format"),
+                &[3001],
+            ),
+            // Deleted leads are ignored, the custom field id is not a lead
+            (
+                "account[subdomain]=city&leads[delete][0][id]=3001&leads[add][0][custom_fields][0][id]=1631153",
+                Some("city"),
+                &[],
+            ),
+            ("leads[add][0][id]=3001", None, &[]),
+            ("%%%", None, &[]),
+        ];
+        for (body, subdomain, ids) in cases {
+            let expected = subdomain.map(|subdomain| Event {
+                subdomain: subdomain.to_string(),
+                lead_ids: ids.to_vec(),
+            });
+            assert_eq!(parse_event(body.as_bytes()), expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn project_subdomain() {
+        assert_eq!(subdomain("https://city.amocrm.ru").as_deref(), Some("city"));
+        assert_eq!(subdomain("https://format.amocrm.ru/").as_deref(), Some("format"));
+        assert_eq!(subdomain("city"), None);
+    }
+}
@@ -1,17 +1,37 @@
 use crate::config::config;
-use crate::model::sync::sync;
+use crate::deadline::calendar;
+use crate::model::deal::deadline;
+use crate::model::reminder::collect_reminders;
+use crate::model::sync::{refresh, sync};
+use crate::model::sync_run::Trigger;
+use crate::model::Db;
+use crate::notify::notify;
 use cron::Schedule;
 use log::debug;
-use sqlx::types::chrono::Local;
+use sqlx::types::chrono::{DateTime, Local};
+use std::future::Future;
 use std::str::FromStr;
-use teloxide::prelude::Requester;
 use teloxide::types::ChatId;
 use teloxide::Bot;
 use tokio::time::sleep;
 
-pub fn do_work(bot: Bot) {
+pub fn do_work(bot: Bot, db: Db) {
+    run_on_schedule(&config().SCHEDULE, bot.clone(), db.clone(), sync_job);
+    run_on_schedule(&config().REFRESH_SCHEDULE, bot.clone(), db.clone(), refresh_job);
+    run_on_schedule(&config().REMINDER_SCHEDULE, bot, db, reminder_job);
+}
+
+pub fn next_run(schedule: &str) -> Option<DateTime<Local>> {
+    Schedule::from_str(schedule).ok()?.upcoming(Local).next()
+}
+
+fn run_on_schedule<F, Fut>(schedule: &str, bot: Bot, db: Db, job: F)
+where
+    F: Fn(Bot, Db) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let schedule = Schedule::from_str(schedule).expect("Schedule is not valid");
     tokio::spawn(async move {
-        let schedule = Schedule::from_str(&config().SCHEDULE).expect("Schedule is not valid");
         debug!("Upcoming fire times:");
         for datetime in schedule.upcoming(Local).take(5) {
             debug!("-> {}", datetime);
@@ -22,27 +42,71 @@ pub fn do_work(bot: Bot) {
             if let Some(next) = schedule.upcoming(Local).next() {
                 let duration = (next - now).to_std().expect("duration cannot be negative");
                 sleep(duration).await;
-                let info = format!("{}: запущена синхронизация", Local::now().format("%d.%m.%Y %H:%M:%S"));
-                debug!("{}", info);
-                bot.send_message(ChatId(config().ADMIN_ID), info)
-                    .await
-                    .expect("Unable to send message to admin");
-                let sync_result = sync().await;
-                match sync_result {
-                    Ok((have_data, data)) => {
-                        if have_data {
-                            bot.send_message(ChatId(config().TG_GROUP_ID), data)
-                                .await
-                                .expect("Unable to send message in group");
-                        }
-                    }
-                    Err(e) => {
-                        bot.send_message(ChatId(config().ADMIN_ID), e.to_string())
-                            .await
-                            .expect("Unable to send message to admin");
-                    }
-                }
+                job(bot.clone(), db.clone()).await;
             }
         }
     });
 }
+
+async fn sync_job(bot: Bot, db: Db) {
+    let info = format!("{}: запущена синхронизация", Local::now().format("%d.%m.%Y %H:%M:%S"));
+    debug!("{}", info);
+    notify(&bot, ChatId(config().ADMIN_ID), &info).await;
+    let sync_result = sync(&db, false, Trigger::Cron).await;
+    match sync_result {
+        Ok(report) => {
+            for e in report.admin_notices() {
+                notify(&bot, ChatId(config().ADMIN_ID), &e).await;
+            }
+            let new_deals = report.have_data().then(|| report.to_string());
+            for text in new_deals.into_iter().chain(report.digest()) {
+                notify(&bot, ChatId(config().TG_GROUP_ID), &text).await;
+            }
+        }
+        Err(e) => notify(&bot, ChatId(config().ADMIN_ID), &e.to_string()).await,
+    }
+}
+
+/// Quiet unless something changed, the admin only hears about errors
+async fn refresh_job(bot: Bot, db: Db) {
+    let report = refresh(&db).await;
+    for e in report.admin_notices() {
+        notify(&bot, ChatId(config().ADMIN_ID), &e).await;
+    }
+    if let Some(digest) = report.digest() {
+        notify(&bot, ChatId(config().TG_GROUP_ID), &digest).await;
+    }
+}
+
+async fn reminder_job(bot: Bot, db: Db) {
+    let days = config().REMINDER_DAYS;
+    let today = Local::now().date_naive();
+    match collect_reminders(&db, today, days, deadline).await {
+        Ok(reminders) => {
+            let missing: Vec<String> = reminders
+                .years
+                .iter()
+                .filter(|year| !calendar().has_year(**year))
+                .map(|year| year.to_string())
+                .collect();
+            if !missing.is_empty() {
+                let text = format!(
+                    "В производственном календаре нет {} г., сроки передачи считаются без праздников. \
+                     Дополните holidays.txt или HOLIDAYS_FILE.",
+                    missing.join(", ")
+                );
+                notify(&bot, ChatId(config().ADMIN_ID), &text).await;
+            }
+            if reminders.is_empty() {
+                debug!("No handover deadlines within {} days", days);
+                return;
+            }
+            notify(&bot, ChatId(config().TG_GROUP_ID), &reminders.group_message(days)).await;
+            // Overdue objects are escalated to the admin as well
+            if let Some(message) = reminders.admin_message() {
+                notify(&bot, ChatId(config().ADMIN_ID), &message).await;
+            }
+        }
+        Err(e) => notify(&bot, ChatId(config().ADMIN_ID), &e.to_string()).await,
+    }
+}